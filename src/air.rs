@@ -0,0 +1,21 @@
+use p3_air::AirBuilder;
+
+/// An `AirBuilder` that also exposes the public values of the proof.
+///
+/// The pinned `p3-air` has no such trait yet, so AIRs reading public values are written against
+/// this one.
+pub trait AirBuilderWithPublicValues: AirBuilder {
+    fn public_values(&self) -> &[Self::Var];
+}
+
+/// An `AirBuilder` for AIRs with a second trace stage, e.g. permutation or LogUp columns, which the
+/// prover fills in after the verifier draws some challenges.
+///
+/// Unlike `p3_air::PermutationAirBuilder`, the second stage and the challenges are base field
+/// elements, as `SymbolicExpression` has no extension field counterpart. An AIR needing extension
+/// field elements spreads each over `D` columns and does the extension arithmetic itself.
+pub trait MultiStageAirBuilder: AirBuilder {
+    fn permutation(&self) -> Self::M;
+
+    fn challenges(&self) -> &[Self::Var];
+}
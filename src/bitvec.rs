@@ -0,0 +1,165 @@
+use core::fmt;
+use p3_field::PrimeField64;
+use std::{
+    marker::PhantomData,
+    ops::{Add, Mul, Neg, Sub},
+};
+use z3::{ast::*, *};
+use z3_sys::*;
+
+use crate::field::FieldElement;
+
+/// A prime field element as a fixed-width bit-vector, reduced with `bvurem` after every operation.
+/// The width is twice that of the modulus, so a product of two canonical values never overflows.
+/// For small fields like BabyBear, Z3 can bit-blast these terms instead of falling back to
+/// nonlinear integer arithmetic.
+#[derive(Clone, Hash, PartialEq, Eq)]
+pub struct BvFelt<'ctx, F: PrimeField64>(BV<'ctx>, PhantomData<F>);
+
+impl<'ctx, F: PrimeField64> BvFelt<'ctx, F> {
+    /// The width of the underlying bit-vectors.
+    pub fn size() -> u32 {
+        2 * (u64::BITS - (F::ORDER_U64 - 1).leading_zeros())
+    }
+
+    pub fn from_bv(x: BV<'ctx>) -> Self {
+        assert_eq!(x.get_size(), Self::size());
+        Self(x, PhantomData::<F>)
+    }
+
+    fn modulus(ctx: &'ctx Context) -> BV<'ctx> {
+        BV::from_u64(ctx, F::ORDER_U64, Self::size())
+    }
+
+    /// Reduces a term known to be below `p^2`.
+    fn reduce(x: BV<'ctx>) -> Self {
+        let p = Self::modulus(x.get_ctx());
+        Self::from_bv(x.bvurem(&p))
+    }
+}
+
+impl<'ctx, F: PrimeField64> FieldElement<'ctx> for BvFelt<'ctx, F> {
+    type F = F;
+
+    fn new_const(solver: &Solver<'ctx>, name: String) -> Self {
+        let x = Self::new_unbounded(solver.get_context(), name);
+        solver.assert(&x.is_canonical());
+        x
+    }
+
+    fn new_unbounded(ctx: &'ctx Context, name: String) -> Self {
+        Self::from_bv(BV::new_const(ctx, name, Self::size()))
+    }
+
+    fn from_u64(ctx: &'ctx Context, u: u64) -> Self {
+        assert!(u < F::ORDER_U64);
+        Self::from_bv(BV::from_u64(ctx, u, Self::size()))
+    }
+
+    fn as_u64(&self) -> Option<u64> {
+        self.0.as_u64()
+    }
+
+    fn is_canonical(&self) -> Bool<'ctx> {
+        self.0.bvult(&Self::modulus(self.get_ctx()))
+    }
+}
+
+impl<'ctx, F: PrimeField64> Ast<'ctx> for BvFelt<'ctx, F> {
+    unsafe fn wrap(ctx: &'ctx Context, ast: Z3_ast) -> Self {
+        Self::from_bv(BV::wrap(ctx, ast))
+    }
+
+    fn get_ctx(&self) -> &'ctx Context {
+        self.0.get_ctx()
+    }
+
+    fn get_z3_ast(&self) -> Z3_ast {
+        self.0.get_z3_ast()
+    }
+}
+
+impl<'ctx, F: PrimeField64> fmt::Debug for BvFelt<'ctx, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        self.0.fmt(f)
+    }
+}
+
+impl<'ctx, F: PrimeField64> fmt::Display for BvFelt<'ctx, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        <Self as fmt::Debug>::fmt(self, f)
+    }
+}
+
+impl<'ctx, F: PrimeField64> Add<&BvFelt<'ctx, F>> for &BvFelt<'ctx, F> {
+    type Output = BvFelt<'ctx, F>;
+
+    fn add(self, other: &BvFelt<'ctx, F>) -> Self::Output {
+        BvFelt::reduce(self.0.bvadd(&other.0))
+    }
+}
+
+impl<'ctx, F: PrimeField64> Add<BvFelt<'ctx, F>> for BvFelt<'ctx, F> {
+    type Output = BvFelt<'ctx, F>;
+
+    fn add(self, other: BvFelt<'ctx, F>) -> Self::Output {
+        &self + &other
+    }
+}
+
+impl<'ctx, F: PrimeField64> Sub<&BvFelt<'ctx, F>> for &BvFelt<'ctx, F> {
+    type Output = BvFelt<'ctx, F>;
+
+    fn sub(self, other: &BvFelt<'ctx, F>) -> Self::Output {
+        // `a + (p - b)` cannot wrap around for canonical operands
+        let p = BvFelt::<F>::modulus(self.get_ctx());
+        BvFelt::reduce(self.0.bvadd(&p.bvsub(&other.0)))
+    }
+}
+
+impl<'ctx, F: PrimeField64> Sub<BvFelt<'ctx, F>> for BvFelt<'ctx, F> {
+    type Output = BvFelt<'ctx, F>;
+
+    fn sub(self, other: BvFelt<'ctx, F>) -> Self::Output {
+        &self - &other
+    }
+}
+
+impl<'ctx, F: PrimeField64> Mul<&BvFelt<'ctx, F>> for &BvFelt<'ctx, F> {
+    type Output = BvFelt<'ctx, F>;
+
+    fn mul(self, other: &BvFelt<'ctx, F>) -> Self::Output {
+        BvFelt::reduce(self.0.bvmul(&other.0))
+    }
+}
+
+impl<'ctx, F: PrimeField64> Mul<BvFelt<'ctx, F>> for BvFelt<'ctx, F> {
+    type Output = BvFelt<'ctx, F>;
+
+    fn mul(self, other: BvFelt<'ctx, F>) -> Self::Output {
+        &self * &other
+    }
+}
+
+impl<'ctx, F: PrimeField64> Neg for BvFelt<'ctx, F> {
+    type Output = BvFelt<'ctx, F>;
+
+    fn neg(self) -> Self::Output {
+        let p = BvFelt::<F>::modulus(self.get_ctx());
+        BvFelt::reduce(p.bvsub(&self.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use p3_baby_bear::BabyBear;
+
+    use super::*;
+    use crate::context::context;
+    use crate::field::tests::cross_check_arithmetic;
+
+    #[test]
+    fn bv_felt_matches_native_arithmetic() {
+        cross_check_arithmetic::<BvFelt<BabyBear>>(context());
+    }
+}
@@ -1,106 +1,785 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::time::{Duration, Instant};
+
 use p3_air::Air;
 use p3_field::PrimeField64;
 use p3_matrix::dense::RowMajorMatrix;
-use p3_matrix::{Matrix, MatrixGet};
-use p3_uni_stark::{SymbolicAirBuilder, SymbolicExpression};
-use z3::ast::{Ast, Bool};
-use z3::{SatResult, Solver};
+use p3_matrix::Matrix;
+use p3_uni_stark::{check_constraints, DebugConstraintBuilder, SymbolicExpression};
+use z3::ast::{Ast, Bool, Int};
+use z3::{Context, Model, Optimize, Params, SatResult, Solver};
 
 use crate::context::context;
-use crate::field::Felt;
+use crate::field::FieldElement;
+use crate::symbolic::{
+    assert_constraints, cells_agree, constraints_hold, exists, extract_trace, forall,
+    pin_and_differ, split_cells, symbolic_constraints, trace_consts, SharedTerms, TraceVars,
+};
+use crate::symbolic_builder::{ExtendedSymbolicAirBuilder, TraceLayout};
+
+/// The outcome of asking Z3 whether the honest trace is the only one satisfying an AIR.
+#[derive(Clone, Debug)]
+pub enum CheckResult<F> {
+    /// No other trace satisfies the constraints.
+    Constrained,
+    /// Some other traces satisfy the constraints, up to `CheckConfig::max_counterexamples` of them.
+    Underconstrained(Vec<Counterexample<F>>),
+    /// The solver gave up; holds `Solver::get_reason_unknown`.
+    Unknown(String),
+    /// Z3 found traces that `p3_uni_stark::check_constraints` rejects, so the Z3 encoding of the
+    /// constraints disagrees with Plonky3's evaluator. The counterexamples it accepts are still
+    /// genuine.
+    EncodingBug {
+        accepted: Vec<Counterexample<F>>,
+        rejected: Vec<Counterexample<F>>,
+    },
+}
+
+/// A check that cannot run on the traces it was given.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CheckError {
+    /// The preprocessed trace does not have as many rows as the main trace.
+    PreprocessedHeight { main: usize, preprocessed: usize },
+    /// The check needs concrete values for every column, but the AIR has a second stage.
+    SecondStage,
+    /// The check needs concrete public values, but they are not `PublicValues::Pinned`.
+    UnpinnedPublicValues,
+    /// The main trace is not as wide as the AIR.
+    MainWidth { air: usize, main: usize },
+}
+
+/// Checks that `preprocessed`, if given, has one row per row of the main trace.
+pub(crate) fn check_preprocessed_height<F>(
+    height: usize,
+    preprocessed: Option<&RowMajorMatrix<F>>,
+) -> Result<(), CheckError> {
+    match preprocessed {
+        Some(p) if p.height() != height => Err(CheckError::PreprocessedHeight {
+            main: height,
+            preprocessed: p.height(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// An alternative trace accepted by the AIR.
+#[derive(Clone, Debug)]
+pub struct Counterexample<F> {
+    pub trace: RowMajorMatrix<F>,
+    /// The `(row, column)` cells in which `trace` differs from the honest trace.
+    pub diff: Vec<(usize, usize)>,
+}
+
+impl<F: PrimeField64> Counterexample<F> {
+    pub fn new(trace: RowMajorMatrix<F>, main: &RowMajorMatrix<F>) -> Self {
+        let width = main.width();
+        let diff = trace
+            .values
+            .iter()
+            .zip(main.values.iter())
+            .enumerate()
+            .filter(|(_, (alt, honest))| alt != honest)
+            .map(|(i, _)| (i / width, i % width))
+            .collect();
+        Self { trace, diff }
+    }
+}
+
+/// How a counterexample is excluded before asking the solver for the next one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Blocking {
+    /// Exclude the whole assignment, so the next trace differs from it in some cell.
+    #[default]
+    Assignment,
+    /// Exclude only the values taken by the cells that differ from the honest trace, so the next
+    /// trace must move some cell to a value not seen in this counterexample's differing cells.
+    DifferingCells,
+}
+
+/// What a minimal counterexample minimizes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Objective {
+    /// The number of cells differing from the honest trace.
+    Cells,
+    /// The number of rows containing a cell that differs from the honest trace.
+    Rows,
+}
+
+/// Part of the trace the prover may choose freely, e.g. a hash preimage. Inputs are pinned to the
+/// honest trace, and only the remaining cells are asked to be determined.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Input {
+    Column(usize),
+    /// A single `(row, column)` cell.
+    Cell(usize, usize),
+}
+
+/// How the public values of the proof are encoded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PublicValues {
+    /// Fixed to the given canonical values.
+    Pinned(Vec<u64>),
+    /// This many public values, and the constraints must hold for every choice of them.
+    Universal(usize),
+    /// This many public values, which the solver may choose. Queries comparing two traces share
+    /// them between the copies, asking whether the public values determine the trace.
+    Free(usize),
+}
+
+impl PublicValues {
+    pub fn num_values(&self) -> usize {
+        match self {
+            PublicValues::Pinned(values) => values.len(),
+            PublicValues::Universal(n) | PublicValues::Free(n) => *n,
+        }
+    }
+}
 
-pub fn check_unconstrained<F, A>(air: &A, main: &RowMajorMatrix<F>)
+impl Default for PublicValues {
+    fn default() -> Self {
+        PublicValues::Pinned(vec![])
+    }
+}
+
+/// How the verifier challenges of a multi-stage AIR are quantified by `check_unconstrained`. The
+/// other checks always let the solver pick the challenges, sharing them between any copies.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Challenges {
+    /// Some challenge admits an alternative trace, so exploiting it may need a lucky challenge.
+    #[default]
+    Exists,
+    /// A single alternative main trace works for every challenge, given a second stage chosen
+    /// after seeing the challenge, so any prover can exploit it.
+    ForAll,
+}
+
+/// How field elements are encoded for Z3.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Encoding {
+    /// Unbounded integers reduced with `mod` after every operation, as `Felt`.
+    #[default]
+    Int,
+    /// Bit-vectors reduced with `bvurem`, as `BvFelt`.
+    BitVec,
+    /// Unreduced integers, reduced once per constraint by a quotient variable, as `QuotientFelt`.
+    Quotient,
+}
+
+impl Encoding {
+    pub const ALL: [Encoding; 3] = [Encoding::Int, Encoding::BitVec, Encoding::Quotient];
+}
+
+/// Calls the function `$check`, generic over a `FieldElement`, with the element type of `encoding`
+/// over the field `$f`, e.g. `dispatch_encoding!(config.encoding, F, check(ctx, config))`.
+macro_rules! dispatch_encoding {
+    ($encoding:expr, $f:ty, $check:ident($($arg:expr),* $(,)?)) => {
+        match $encoding {
+            $crate::check_unconstrained::Encoding::Int => {
+                $check::<$crate::field::Felt<$f>>($($arg),*)
+            }
+            $crate::check_unconstrained::Encoding::BitVec => {
+                $check::<$crate::bitvec::BvFelt<$f>>($($arg),*)
+            }
+            $crate::check_unconstrained::Encoding::Quotient => {
+                $check::<$crate::quotient::QuotientFelt<$f>>($($arg),*)
+            }
+        }
+    };
+}
+pub(crate) use dispatch_encoding;
+
+#[derive(Clone, Debug)]
+pub struct CheckConfig {
+    /// The number of distinct counterexamples to collect before stopping.
+    pub max_counterexamples: usize,
+    pub blocking: Blocking,
+    /// If set, each counterexample is found with `z3::Optimize` and is minimal for the objective.
+    pub objective: Option<Objective>,
+    pub inputs: Vec<Input>,
+    pub public_values: PublicValues,
+    /// The width of the second trace stage of a multi-stage AIR. Its cells are always symbolic, as
+    /// no honest values are given for them.
+    pub permutation_width: usize,
+    pub num_challenges: usize,
+    pub challenges: Challenges,
+    pub encoding: Encoding,
+    /// Per-query timeout in milliseconds. A query that times out is reported as unknown.
+    pub timeout_ms: Option<u32>,
+    /// Per-query Z3 resource limit, which unlike the timeout is deterministic across machines.
+    pub rlimit: Option<u32>,
+}
+
+impl Default for CheckConfig {
+    fn default() -> Self {
+        Self {
+            max_counterexamples: 1,
+            blocking: Blocking::default(),
+            objective: None,
+            inputs: vec![],
+            public_values: PublicValues::default(),
+            permutation_width: 0,
+            num_challenges: 0,
+            challenges: Challenges::default(),
+            encoding: Encoding::default(),
+            timeout_ms: None,
+            rlimit: None,
+        }
+    }
+}
+
+impl CheckConfig {
+    /// Whether the cell at `(row, col)` is one of the designated inputs.
+    pub fn is_input(&self, row: usize, col: usize) -> bool {
+        self.inputs.iter().any(|input| match *input {
+            Input::Column(c) => c == col,
+            Input::Cell(r, c) => (r, c) == (row, col),
+        })
+    }
+
+    /// Whether the whole column `col` is a designated input.
+    pub fn is_input_column(&self, col: usize) -> bool {
+        self.inputs.contains(&Input::Column(col))
+    }
+
+    /// Splits the per-cell `items` of a trace `width` columns wide into those of the input cells
+    /// and those of the rest.
+    pub(crate) fn split_inputs<T>(
+        &self,
+        width: usize,
+        items: impl IntoIterator<Item = T>,
+    ) -> (Vec<T>, Vec<T>) {
+        split_cells(width, items, |row, col| self.is_input(row, col))
+    }
+
+    /// The layout of a trace with the given main and preprocessed widths.
+    pub(crate) fn layout(&self, width: usize, preprocessed_width: usize) -> TraceLayout {
+        TraceLayout {
+            width,
+            preprocessed_width,
+            permutation_width: self.permutation_width,
+            num_public_values: self.public_values.num_values(),
+            num_challenges: self.num_challenges,
+        }
+    }
+
+    /// The Z3 parameters carrying the configured limits.
+    pub(crate) fn params<'ctx>(&self, ctx: &'ctx Context) -> Params<'ctx> {
+        let mut params = Params::new(ctx);
+        if let Some(timeout_ms) = self.timeout_ms {
+            params.set_u32("timeout", timeout_ms);
+        }
+        if let Some(rlimit) = self.rlimit {
+            params.set_u32("rlimit", rlimit);
+        }
+        params
+    }
+
+    /// A new solver with the configured limits.
+    pub(crate) fn solver<'ctx>(&self, ctx: &'ctx Context) -> Solver<'ctx> {
+        let solver = Solver::new(ctx);
+        solver.set_params(&self.params(ctx));
+        solver
+    }
+}
+
+/// Asks whether any trace other than `main` satisfies the constraints of `air`. The cells of
+/// `preprocessed`, if given, are fixed constants the alternative trace cannot change. The second
+/// stage and challenges of a multi-stage AIR are quantified as set by `CheckConfig::challenges`.
+pub fn check_unconstrained<F, A>(
+    air: &A,
+    main: &RowMajorMatrix<F>,
+    preprocessed: Option<&RowMajorMatrix<F>>,
+    config: &CheckConfig,
+) -> Result<CheckResult<F>, CheckError>
 where
     F: PrimeField64,
-    A: Air<SymbolicAirBuilder<F>>,
+    A: Air<ExtendedSymbolicAirBuilder<F>>,
 {
-    let ctx = context();
-    let solver = Solver::new(ctx);
+    check_preprocessed_height(main.height(), preprocessed)?;
+    let preprocessed_width = preprocessed.map_or(0, |p| p.width());
+    let constraints = symbolic_constraints(air, config.layout(main.width(), preprocessed_width));
+    let result = dispatch_encoding!(
+        config.encoding,
+        F,
+        find_counterexamples(context(), &constraints, main, preprocessed, config)
+    );
+    Ok(result)
+}
 
-    let width = main.width();
-    let height = main.height();
+/// As `check_unconstrained`, but also runs every counterexample through
+/// `p3_uni_stark::check_constraints`, as a self-check of the Z3 encoding. Any it rejects are
+/// reported as `CheckResult::EncodingBug`.
+///
+/// `DebugConstraintBuilder` provides no preprocessed columns, public values or second stage, so
+/// only AIRs reading none of them can be checked this way.
+pub fn check_unconstrained_validated<F, A>(
+    air: &A,
+    main: &RowMajorMatrix<F>,
+    config: &CheckConfig,
+) -> Result<CheckResult<F>, CheckError>
+where
+    F: PrimeField64,
+    A: Air<ExtendedSymbolicAirBuilder<F>> + for<'a> Air<DebugConstraintBuilder<'a, F>>,
+{
+    let result = check_unconstrained(air, main, None, config)?;
+    Ok(validate_counterexamples(air, result))
+}
 
-    let mut builder = SymbolicAirBuilder::new(width);
-    air.eval(&mut builder);
-    let constraints = builder.constraints();
+/// The result of `check_unconstrained` under one `Encoding`, and how long the solver took.
+#[derive(Clone, Debug)]
+pub struct EncodingRun<F> {
+    pub encoding: Encoding,
+    pub result: CheckResult<F>,
+    pub elapsed: Duration,
+}
 
-    let vars = RowMajorMatrix::new(
-        (0..width * height)
-            .map(|row| {
-                Felt::<F>::new_const(&solver, format!("T[{}][{}]", row / width, row % width))
-            })
-            .collect(),
-        width,
-    );
+/// The runs of `compare_encodings`, one per `Encoding` in the order of `Encoding::ALL`.
+#[derive(Clone, Debug)]
+pub struct EncodingComparison<F> {
+    pub runs: Vec<EncodingRun<F>>,
+    /// The first two encodings that disagree on whether the trace is constrained, which points at
+    /// a bug in one of them. Encodings on which the solver gives up are skipped.
+    pub disagreement: Option<(Encoding, Encoding)>,
+}
 
-    (0..height).for_each(|i| {
-        constraints.iter().for_each(|constraint| {
-            let exp = parse_symbolic_expression(constraint, &solver, &vars, i, height);
-            exp.assert_zero(&solver);
+/// Runs `check_unconstrained` under every `Encoding`, timing each run, so a single pass both
+/// cross-checks the encodings and benchmarks them.
+pub fn compare_encodings<F, A>(
+    air: &A,
+    main: &RowMajorMatrix<F>,
+    preprocessed: Option<&RowMajorMatrix<F>>,
+    config: &CheckConfig,
+) -> Result<EncodingComparison<F>, CheckError>
+where
+    F: PrimeField64,
+    A: Air<ExtendedSymbolicAirBuilder<F>>,
+{
+    let mut runs = vec![];
+    for encoding in Encoding::ALL {
+        let config = CheckConfig {
+            encoding,
+            ..config.clone()
+        };
+        let start = Instant::now();
+        let result = check_unconstrained(air, main, preprocessed, &config)?;
+        runs.push(EncodingRun {
+            encoding,
+            result,
+            elapsed: start.elapsed(),
         });
-    });
-
-    // Ignore trace as solution
-    let solution = vars
-        .values
-        .iter()
-        .zip(main.values.iter())
-        .map(|(var, val)| var._eq(&Felt::from_u64(ctx, val.as_canonical_u64())).not())
-        .collect::<Vec<_>>();
-    solver.assert(&Bool::or(ctx, &solution));
-
-    match solver.check() {
-        SatResult::Sat => {
-            let model = solver.get_model().unwrap();
-            for i in 0..height {
-                for j in 0..width {
-                    print!("{} ", model.eval(&vars.get(i, j), true).unwrap());
-                }
-                println!()
+    }
+
+    let mut first = None;
+    let mut disagreement = None;
+    for run in &runs {
+        let constrained = match run.result {
+            CheckResult::Constrained => true,
+            CheckResult::Underconstrained(_) | CheckResult::EncodingBug { .. } => false,
+            CheckResult::Unknown(_) => continue,
+        };
+        match first {
+            None => first = Some((run.encoding, constrained)),
+            Some((other, expected)) if expected != constrained => {
+                disagreement = Some((other, run.encoding));
+                break;
             }
+            Some(_) => {}
         }
-        _ => println!("No solution"),
     }
+    Ok(EncodingComparison { runs, disagreement })
 }
 
-fn parse_symbolic_expression<'ctx, F>(
-    exp: &SymbolicExpression<F>,
-    solver: &'ctx Solver<'ctx>,
-    vars: &RowMajorMatrix<Felt<'ctx, F>>,
-    row: usize,
-    height: usize,
-) -> Felt<'ctx, F>
+/// Splits the counterexamples of `result` by whether `p3_uni_stark::check_constraints` accepts
+/// them.
+///
+/// `check_constraints` reports a failure by panicking, and the panic hook is process-global, so
+/// while this runs, panic messages from other threads, e.g. other tests, are silenced too.
+fn validate_counterexamples<F, A>(air: &A, result: CheckResult<F>) -> CheckResult<F>
 where
     F: PrimeField64,
+    A: for<'a> Air<DebugConstraintBuilder<'a, F>>,
 {
-    match exp {
-        SymbolicExpression::Variable(var) => {
-            if var.is_next {
-                vars.get((row + 1) % height, var.column)
-            } else {
-                vars.get(row, var.column)
+    let CheckResult::Underconstrained(counterexamples) = result else {
+        return result;
+    };
+    // `check_constraints` panics on the first constraint that does not vanish, so silence the
+    // panic message while it runs
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let (accepted, rejected): (Vec<_>, Vec<_>) =
+        counterexamples.into_iter().partition(|counterexample| {
+            panic::catch_unwind(AssertUnwindSafe(|| {
+                check_constraints(air, &counterexample.trace)
+            }))
+            .is_ok()
+        });
+    panic::set_hook(hook);
+
+    if rejected.is_empty() {
+        CheckResult::Underconstrained(accepted)
+    } else {
+        CheckResult::EncodingBug { accepted, rejected }
+    }
+}
+
+/// The assertions behind `check_unconstrained`, before any counterexample is blocked.
+pub(crate) struct UniquenessQuery<'ctx, E> {
+    pub solver: Solver<'ctx>,
+    /// The alternative trace `T[r][c]`.
+    pub vars: RowMajorMatrix<E>,
+    /// Whether each cell of `vars` differs from the honest trace.
+    pub differs: Vec<Bool<'ctx>>,
+}
+
+/// Asserts the constraints on a symbolic trace, pins its inputs to the honest trace, and asks for
+/// some other cell to differ.
+pub(crate) fn uniqueness_query<'ctx, E>(
+    ctx: &'ctx Context,
+    constraints: &[SymbolicExpression<E::F>],
+    main: &RowMajorMatrix<E::F>,
+    preprocessed: Option<&RowMajorMatrix<E::F>>,
+    config: &CheckConfig,
+) -> UniquenessQuery<'ctx, E>
+where
+    E: FieldElement<'ctx>,
+{
+    let solver = config.solver(ctx);
+
+    let quantified = config.challenges == Challenges::ForAll;
+    let vars = TraceVars::<E>::new(
+        &solver,
+        ("T", "S"),
+        main.width(),
+        main.height(),
+        config,
+        quantified,
+    );
+    let shared = SharedTerms::<E>::new(&solver, preprocessed, config, quantified);
+    let terms = shared.terms(&vars);
+    match config.challenges {
+        Challenges::Exists => assert_constraints(&solver, constraints, &terms),
+        Challenges::ForAll => {
+            // forall challenges. exists second stage. constraints
+            let holds = constraints_hold(ctx, constraints, &terms);
+            let permutation = vars
+                .permutation
+                .as_ref()
+                .map_or(vec![], |p| p.values.clone());
+            let bound = [
+                shared.challenges.as_slice(),
+                shared.public_values.universal_values(),
+            ]
+            .concat();
+            solver.assert(&forall(ctx, &bound, &exists(ctx, &permutation, &holds)));
+        }
+    }
+
+    // Pin the inputs, and ask for some other cell to differ from the honest trace
+    let honest = trace_consts::<E>(ctx, main);
+    let agrees = cells_agree(&vars.main, &honest);
+    let differs = agrees.iter().map(Bool::not).collect();
+    pin_and_differ(&solver, config.split_inputs(main.width(), agrees));
+
+    UniquenessQuery {
+        solver,
+        vars: vars.main,
+        differs,
+    }
+}
+
+fn find_counterexamples<'ctx, E>(
+    ctx: &'ctx Context,
+    constraints: &[SymbolicExpression<E::F>],
+    main: &RowMajorMatrix<E::F>,
+    preprocessed: Option<&RowMajorMatrix<E::F>>,
+    config: &CheckConfig,
+) -> CheckResult<E::F>
+where
+    E: FieldElement<'ctx>,
+{
+    let UniquenessQuery {
+        solver,
+        vars,
+        differs,
+    } = uniqueness_query::<E>(ctx, constraints, main, preprocessed, config);
+    let width = main.width();
+
+    let optimize;
+    let backend = match config.objective {
+        None => Backend::Solver(&solver),
+        Some(objective) => {
+            optimize = Optimize::new(ctx);
+            optimize.set_params(&config.params(ctx));
+            for assertion in solver.get_assertions() {
+                optimize.assert(&assertion);
             }
+            optimize.minimize(&objective_term(ctx, &differs, width, objective));
+            Backend::Optimize(&optimize)
         }
-        SymbolicExpression::IsFirstRow => Felt::from_bool(solver.get_context(), row == 0),
-        SymbolicExpression::IsLastRow => Felt::from_bool(solver.get_context(), row == height - 1),
-        SymbolicExpression::IsTransition => {
-            Felt::from_bool(solver.get_context(), row != height - 1)
+    };
+
+    let mut counterexamples = vec![];
+    while counterexamples.len() < config.max_counterexamples {
+        match backend.check() {
+            SatResult::Sat => {
+                let model = backend.get_model().unwrap();
+                let counterexample = Counterexample::new(extract_trace(&model, &vars), main);
+                backend.assert(&blocking_clause(
+                    ctx,
+                    &vars,
+                    &counterexample,
+                    config.blocking,
+                ));
+                counterexamples.push(counterexample);
+            }
+            SatResult::Unsat => break,
+            // Keep whatever was found before the solver gave up.
+            SatResult::Unknown if !counterexamples.is_empty() => break,
+            SatResult::Unknown => {
+                return CheckResult::Unknown(backend.get_reason_unknown().unwrap_or_default())
+            }
+        }
+    }
+
+    if counterexamples.is_empty() {
+        CheckResult::Constrained
+    } else {
+        CheckResult::Underconstrained(counterexamples)
+    }
+}
+
+/// The solver a query runs on: `Optimize` when counterexamples should be minimal.
+enum Backend<'a, 'ctx> {
+    Solver(&'a Solver<'ctx>),
+    Optimize(&'a Optimize<'ctx>),
+}
+
+impl<'a, 'ctx> Backend<'a, 'ctx> {
+    fn assert(&self, ast: &Bool<'ctx>) {
+        match self {
+            Backend::Solver(solver) => solver.assert(ast),
+            Backend::Optimize(optimize) => optimize.assert(ast),
         }
-        SymbolicExpression::Constant(f) => Felt::from_f(solver.get_context(), *f),
-        SymbolicExpression::Add { x, y, .. } => {
-            parse_symbolic_expression(x, solver, vars, row, height)
-                + parse_symbolic_expression(y, solver, vars, row, height)
+    }
+
+    fn check(&self) -> SatResult {
+        match self {
+            Backend::Solver(solver) => solver.check(),
+            Backend::Optimize(optimize) => optimize.check(&[]),
+        }
+    }
+
+    fn get_model(&self) -> Option<Model<'ctx>> {
+        match self {
+            Backend::Solver(solver) => solver.get_model(),
+            Backend::Optimize(optimize) => optimize.get_model(),
         }
-        SymbolicExpression::Sub { x, y, .. } => {
-            parse_symbolic_expression(x, solver, vars, row, height)
-                - parse_symbolic_expression(y, solver, vars, row, height)
+    }
+
+    fn get_reason_unknown(&self) -> Option<String> {
+        match self {
+            Backend::Solver(solver) => solver.get_reason_unknown(),
+            Backend::Optimize(optimize) => optimize.get_reason_unknown(),
+        }
+    }
+}
+
+/// Counts the differing cells, or the rows containing one, given a `differs` flag per cell.
+fn objective_term<'ctx>(
+    ctx: &'ctx Context,
+    differs: &[Bool<'ctx>],
+    width: usize,
+    objective: Objective,
+) -> Int<'ctx> {
+    let indicators = match objective {
+        Objective::Cells => differs.to_vec(),
+        Objective::Rows => differs
+            .chunks(width)
+            .map(|row| Bool::or(ctx, row))
+            .collect(),
+    };
+    let zero = Int::from_u64(ctx, 0);
+    let one = Int::from_u64(ctx, 1);
+    Int::add(
+        ctx,
+        &indicators
+            .iter()
+            .map(|indicator| indicator.ite(&one, &zero))
+            .collect::<Vec<_>>(),
+    )
+}
+
+/// A clause that rules out `counterexample` as a future model.
+fn blocking_clause<'ctx, E>(
+    ctx: &'ctx Context,
+    vars: &RowMajorMatrix<E>,
+    counterexample: &Counterexample<E::F>,
+    blocking: Blocking,
+) -> Bool<'ctx>
+where
+    E: FieldElement<'ctx>,
+{
+    let width = vars.width();
+    let cells = match blocking {
+        Blocking::Assignment => (0..vars.values.len()).collect::<Vec<_>>(),
+        Blocking::DifferingCells => counterexample
+            .diff
+            .iter()
+            .map(|&(row, col)| row * width + col)
+            .collect(),
+    };
+    let clause = cells
+        .into_iter()
+        .map(|i| {
+            let value = E::from_f(ctx, counterexample.trace.values[i]);
+            vars.values[i]._eq(&value).not()
+        })
+        .collect::<Vec<_>>();
+    Bool::or(ctx, &clause)
+}
+
+#[cfg(test)]
+mod tests {
+    use p3_air::{AirBuilder, BaseAir, PairBuilder};
+    use p3_baby_bear::BabyBear;
+    use p3_field::AbstractField;
+    use p3_matrix::MatrixRowSlices;
+
+    use super::*;
+    use crate::air::{AirBuilderWithPublicValues, MultiStageAirBuilder};
+
+    type Val = BabyBear;
+
+    /// A two-column AIR reading every part of the trace. Column `a` starts at zero and adds the
+    /// preprocessed column `q` on each transition, but the transition constraint is scaled by the
+    /// public value, so it only binds when that is nonzero. The second stage `s` must equal
+    /// `c * (b - a)` for the challenge `c` and vanish, which ties column `b` to `a` unless `c` is
+    /// zero.
+    struct StagedAir {}
+
+    impl<F> BaseAir<F> for StagedAir {
+        fn width(&self) -> usize {
+            2
         }
-        SymbolicExpression::Neg { x, .. } => {
-            -parse_symbolic_expression(x, solver, vars, row, height)
+    }
+
+    impl<AB> Air<AB> for StagedAir
+    where
+        AB: PairBuilder + AirBuilderWithPublicValues + MultiStageAirBuilder,
+    {
+        fn eval(&self, builder: &mut AB) {
+            let main = builder.main();
+            let preprocessed = builder.preprocessed();
+            let permutation = builder.permutation();
+            let public_value: AB::Expr = builder.public_values()[0].into();
+            let challenge: AB::Expr = builder.challenges()[0].into();
+
+            let local_a: AB::Expr = main.row_slice(0)[0].into();
+            let local_b: AB::Expr = main.row_slice(0)[1].into();
+            let next_a: AB::Expr = main.row_slice(1)[0].into();
+            let q: AB::Expr = preprocessed.row_slice(0)[0].into();
+            let s: AB::Expr = permutation.row_slice(0)[0].into();
+
+            builder.when_first_row().assert_zero(local_a.clone());
+            builder
+                .when_transition()
+                .assert_zero(public_value * (next_a - local_a.clone() - q));
+            builder.assert_eq(s.clone(), challenge * (local_b - local_a));
+            builder.assert_zero(s);
         }
-        SymbolicExpression::Mul { x, y, .. } => {
-            parse_symbolic_expression(x, solver, vars, row, height)
-                * parse_symbolic_expression(y, solver, vars, row, height)
+    }
+
+    fn matrix(values: &[u64], width: usize) -> RowMajorMatrix<Val> {
+        RowMajorMatrix::new(
+            values.iter().map(|&v| Val::from_canonical_u64(v)).collect(),
+            width,
+        )
+    }
+
+    /// The honest trace, with `b` a copy of `a`, and its preprocessed column.
+    fn traces() -> (RowMajorMatrix<Val>, RowMajorMatrix<Val>) {
+        let main = matrix(&[0, 0, 1, 1, 3, 3, 6, 6], 2);
+        let preprocessed = matrix(&[1, 2, 3, 0], 1);
+        (main, preprocessed)
+    }
+
+    fn check(public_values: PublicValues, challenges: Challenges) -> CheckResult<Val> {
+        let (main, preprocessed) = traces();
+        let config = CheckConfig {
+            public_values,
+            permutation_width: 1,
+            num_challenges: 1,
+            challenges,
+            ..CheckConfig::default()
+        };
+        check_unconstrained(&StagedAir {}, &main, Some(&preprocessed), &config).unwrap()
+    }
+
+    /// The cells the single counterexample of `result` differs in.
+    fn diff(result: CheckResult<Val>) -> Vec<(usize, usize)> {
+        match result {
+            CheckResult::Underconstrained(counterexamples) => {
+                assert_eq!(counterexamples.len(), 1);
+                counterexamples[0].diff.clone()
+            }
+            result => panic!("expected a counterexample, got {:?}", result),
         }
     }
+
+    #[test]
+    fn pinned_public_value_determines_the_trace() {
+        let result = check(PublicValues::Pinned(vec![1]), Challenges::ForAll);
+        assert!(matches!(result, CheckResult::Constrained), "{:?}", result);
+    }
+
+    #[test]
+    fn pinned_zero_public_value_frees_all_but_the_first_row() {
+        let diff = diff(check(PublicValues::Pinned(vec![0]), Challenges::ForAll));
+        assert!(!diff.is_empty());
+        assert!(diff.iter().all(|&(row, _)| row > 0), "{:?}", diff);
+    }
+
+    #[test]
+    fn universal_public_value_must_hold_for_every_value() {
+        let result = check(PublicValues::Universal(1), Challenges::ForAll);
+        assert!(matches!(result, CheckResult::Constrained), "{:?}", result);
+
+        // Without the universal challenge, only `b` can move
+        let diff = diff(check(PublicValues::Universal(1), Challenges::Exists));
+        assert!(diff.iter().all(|&(_, col)| col == 1), "{:?}", diff);
+    }
+
+    #[test]
+    fn free_public_value_is_chosen_by_the_solver() {
+        let diff = diff(check(PublicValues::Free(1), Challenges::ForAll));
+        assert!(!diff.is_empty());
+        assert!(diff.iter().all(|&(row, _)| row > 0), "{:?}", diff);
+    }
+
+    #[test]
+    fn existential_challenge_frees_the_second_column() {
+        let diff = diff(check(PublicValues::Pinned(vec![1]), Challenges::Exists));
+        assert!(!diff.is_empty());
+        assert!(diff.iter().all(|&(_, col)| col == 1), "{:?}", diff);
+    }
+
+    #[test]
+    fn preprocessed_height_must_match() {
+        let (main, _) = traces();
+        let preprocessed = matrix(&[1, 2, 3], 1);
+        let config = CheckConfig {
+            public_values: PublicValues::Pinned(vec![1]),
+            permutation_width: 1,
+            num_challenges: 1,
+            ..CheckConfig::default()
+        };
+        let result = check_unconstrained(&StagedAir {}, &main, Some(&preprocessed), &config);
+        assert_eq!(
+            result.unwrap_err(),
+            CheckError::PreprocessedHeight {
+                main: 4,
+                preprocessed: 3
+            }
+        );
+    }
 }
@@ -1,13 +1,11 @@
-use std::sync::OnceLock;
 use z3::{Config, Context};
 
-struct SafeContext(Context);
-unsafe impl Send for SafeContext {}
-unsafe impl Sync for SafeContext {}
+thread_local! {
+    // A Z3 context must not be shared between threads, e.g. tests running in parallel, so each
+    // thread gets its own, leaked to give its terms a `'static` lifetime
+    static CONTEXT: &'static Context = Box::leak(Box::new(Context::new(&Config::new())));
+}
 
 pub(crate) fn context() -> &'static Context {
-    static CONTEXT: OnceLock<SafeContext> = OnceLock::new();
-    &CONTEXT
-        .get_or_init(|| SafeContext(Context::new(&Config::new())))
-        .0
+    CONTEXT.with(|ctx| *ctx)
 }
@@ -0,0 +1,285 @@
+use p3_air::Air;
+use p3_field::PrimeField64;
+use p3_matrix::dense::RowMajorMatrix;
+use p3_matrix::Matrix;
+use p3_uni_stark::SymbolicExpression;
+use z3::ast::Ast;
+use z3::{Context, SatResult};
+
+use crate::check_unconstrained::{
+    check_preprocessed_height, dispatch_encoding, CheckConfig, CheckError, Counterexample,
+};
+use crate::context::context;
+use crate::field::FieldElement;
+use crate::symbolic::{
+    assert_constraints, assert_tracked_constraints, cells_agree, extract_trace, pin_and_differ,
+    symbolic_constraints, trace_consts, SharedTerms, TraceVars,
+};
+use crate::symbolic_builder::ExtendedSymbolicAirBuilder;
+
+/// Whether a single cell of the honest trace is forced by the constraints.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CellStatus {
+    /// Every satisfying trace agrees with the honest trace on this cell.
+    Determined,
+    /// Some satisfying trace differs from the honest trace on this cell.
+    Free,
+    /// The solver gave up on this cell, e.g. on hitting `CheckConfig::timeout_ms`.
+    Unknown,
+    /// The cell is a designated input, pinned to the honest trace.
+    Input,
+}
+
+/// Asks, for every cell of `main`, whether that cell can take a different value while all
+/// constraints still hold. The result has the same shape as `main`.
+pub fn determinism_map<F, A>(
+    air: &A,
+    main: &RowMajorMatrix<F>,
+    preprocessed: Option<&RowMajorMatrix<F>>,
+    config: &CheckConfig,
+) -> Result<RowMajorMatrix<CellStatus>, CheckError>
+where
+    F: PrimeField64,
+    A: Air<ExtendedSymbolicAirBuilder<F>>,
+{
+    check_preprocessed_height(main.height(), preprocessed)?;
+    let preprocessed_width = preprocessed.map_or(0, |p| p.width());
+    let constraints = symbolic_constraints(air, config.layout(main.width(), preprocessed_width));
+    let map = dispatch_encoding!(
+        config.encoding,
+        F,
+        map_cells(context(), &constraints, main, preprocessed, config)
+    );
+    Ok(map)
+}
+
+fn map_cells<'ctx, E>(
+    ctx: &'ctx Context,
+    constraints: &[SymbolicExpression<E::F>],
+    main: &RowMajorMatrix<E::F>,
+    preprocessed: Option<&RowMajorMatrix<E::F>>,
+    config: &CheckConfig,
+) -> RowMajorMatrix<CellStatus>
+where
+    E: FieldElement<'ctx>,
+{
+    let solver = config.solver(ctx);
+
+    let width = main.width();
+    let height = main.height();
+
+    let shared = SharedTerms::<E>::new(&solver, preprocessed, config, false);
+    let copy = TraceVars::<E>::new(&solver, ("T", "S"), width, height, config, false);
+    let vars = &copy.main;
+    let terms = shared.terms(&copy);
+    assert_constraints(&solver, constraints, &terms);
+    let honest = trace_consts::<E>(ctx, main);
+
+    let mut statuses = vec![None; width * height];
+    for i in 0..width * height {
+        if config.is_input(i / width, i % width) {
+            vars.values[i].assert_eq(&solver, &honest.values[i]);
+            statuses[i] = Some(CellStatus::Input);
+        }
+    }
+
+    for i in 0..width * height {
+        if statuses[i].is_some() {
+            continue;
+        }
+
+        solver.push();
+        solver.assert(&vars.values[i]._eq(&honest.values[i]).not());
+        let status = match solver.check() {
+            SatResult::Sat => {
+                // Every cell moved by this model is free too, which saves a query for each.
+                let model = solver.get_model().unwrap();
+                let counterexample = Counterexample::new(extract_trace(&model, vars), main);
+                for (row, col) in counterexample.diff {
+                    statuses[row * width + col] = Some(CellStatus::Free);
+                }
+                CellStatus::Free
+            }
+            SatResult::Unsat => CellStatus::Determined,
+            SatResult::Unknown => CellStatus::Unknown,
+        };
+        statuses[i] = Some(status);
+        solver.pop(1);
+    }
+
+    RowMajorMatrix::new(statuses.into_iter().map(Option::unwrap).collect(), width)
+}
+
+/// Why a single cell of the honest trace is or is not forced by the constraints.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CellExplanation {
+    /// The cell is forced by the `(constraint index, row)` instances of an unsat core. The core is
+    /// sufficient but not necessarily minimal.
+    Determined(Vec<(usize, usize)>),
+    Free,
+    /// The solver gave up; holds `Solver::get_reason_unknown`.
+    Unknown(String),
+    Input,
+}
+
+/// Asks which constraint instances force the cell at `(row, col)` of `main`. Each constraint on
+/// each row is asserted under its own assumption, so when the cell cannot differ from the honest
+/// trace, the unsat core names the instances doing the work. Constraints that never show up in a
+/// core carry no weight for that cell.
+pub fn explain_cell<F, A>(
+    air: &A,
+    main: &RowMajorMatrix<F>,
+    preprocessed: Option<&RowMajorMatrix<F>>,
+    (row, col): (usize, usize),
+    config: &CheckConfig,
+) -> Result<CellExplanation, CheckError>
+where
+    F: PrimeField64,
+    A: Air<ExtendedSymbolicAirBuilder<F>>,
+{
+    check_preprocessed_height(main.height(), preprocessed)?;
+    if config.is_input(row, col) {
+        return Ok(CellExplanation::Input);
+    }
+
+    let preprocessed_width = preprocessed.map_or(0, |p| p.width());
+    let constraints = symbolic_constraints(air, config.layout(main.width(), preprocessed_width));
+    let cell = row * main.width() + col;
+    let explanation = dispatch_encoding!(
+        config.encoding,
+        F,
+        explain(context(), &constraints, main, preprocessed, cell, config)
+    );
+    Ok(explanation)
+}
+
+fn explain<'ctx, E>(
+    ctx: &'ctx Context,
+    constraints: &[SymbolicExpression<E::F>],
+    main: &RowMajorMatrix<E::F>,
+    preprocessed: Option<&RowMajorMatrix<E::F>>,
+    cell: usize,
+    config: &CheckConfig,
+) -> CellExplanation
+where
+    E: FieldElement<'ctx>,
+{
+    let solver = config.solver(ctx);
+
+    let width = main.width();
+    let height = main.height();
+
+    let shared = SharedTerms::<E>::new(&solver, preprocessed, config, false);
+    let copy = TraceVars::<E>::new(&solver, ("T", "S"), width, height, config, false);
+    let vars = &copy.main;
+    let terms = shared.terms(&copy);
+    let literals = assert_tracked_constraints(&solver, constraints, &terms);
+    let honest = trace_consts::<E>(ctx, main);
+
+    for i in 0..width * height {
+        if config.is_input(i / width, i % width) {
+            vars.values[i].assert_eq(&solver, &honest.values[i]);
+        }
+    }
+    solver.assert(&vars.values[cell]._eq(&honest.values[cell]).not());
+
+    let assumptions = literals.iter().map(|(_, l)| l.clone()).collect::<Vec<_>>();
+    match solver.check_assumptions(&assumptions) {
+        SatResult::Sat => CellExplanation::Free,
+        SatResult::Unsat => {
+            let core = solver.get_unsat_core();
+            CellExplanation::Determined(
+                literals
+                    .into_iter()
+                    .filter(|(_, literal)| core.contains(literal))
+                    .map(|(instance, _)| instance)
+                    .collect(),
+            )
+        }
+        SatResult::Unknown => {
+            CellExplanation::Unknown(solver.get_reason_unknown().unwrap_or_default())
+        }
+    }
+}
+
+/// The outcome of comparing two fully symbolic traces that agree on the inputs.
+#[derive(Clone, Debug)]
+pub enum DeterminismResult<F> {
+    /// Any two traces that agree on the inputs agree everywhere.
+    Deterministic,
+    /// Two traces that agree on the inputs but differ in the `(row, column)` cells of `diff`.
+    Nondeterministic {
+        left: RowMajorMatrix<F>,
+        right: RowMajorMatrix<F>,
+        diff: Vec<(usize, usize)>,
+    },
+    /// The solver gave up; holds `Solver::get_reason_unknown`.
+    Unknown(String),
+}
+
+/// Asks whether two traces of the given height can satisfy the constraints, share the designated
+/// inputs and still differ in some other cell. Unlike `check_unconstrained`, this needs no honest
+/// trace and so covers every input at once. `preprocessed`, if given, must have the same height.
+pub fn check_deterministic<F, A>(
+    air: &A,
+    height: usize,
+    preprocessed: Option<&RowMajorMatrix<F>>,
+    config: &CheckConfig,
+) -> Result<DeterminismResult<F>, CheckError>
+where
+    F: PrimeField64,
+    A: Air<ExtendedSymbolicAirBuilder<F>>,
+{
+    check_preprocessed_height(height, preprocessed)?;
+    let width = air.width();
+    let preprocessed_width = preprocessed.map_or(0, |p| p.width());
+    let constraints = symbolic_constraints(air, config.layout(width, preprocessed_width));
+    let result = dispatch_encoding!(
+        config.encoding,
+        F,
+        compare_copies(context(), &constraints, width, height, preprocessed, config)
+    );
+    Ok(result)
+}
+
+fn compare_copies<'ctx, E>(
+    ctx: &'ctx Context,
+    constraints: &[SymbolicExpression<E::F>],
+    width: usize,
+    height: usize,
+    preprocessed: Option<&RowMajorMatrix<E::F>>,
+    config: &CheckConfig,
+) -> DeterminismResult<E::F>
+where
+    E: FieldElement<'ctx>,
+{
+    let solver = config.solver(ctx);
+
+    // Each copy has its own second stage, but both see the same challenges
+    let shared = SharedTerms::<E>::new(&solver, preprocessed, config, false);
+    let left = TraceVars::<E>::new(&solver, ("L", "SL"), width, height, config, false);
+    let right = TraceVars::<E>::new(&solver, ("R", "SR"), width, height, config, false);
+    for copy in [&left, &right] {
+        assert_constraints(&solver, constraints, &shared.terms(copy));
+    }
+
+    // The copies share their inputs and must differ somewhere else
+    pin_and_differ(
+        &solver,
+        config.split_inputs(width, cells_agree(&left.main, &right.main)),
+    );
+
+    match solver.check() {
+        SatResult::Sat => {
+            let model = solver.get_model().unwrap();
+            let left = extract_trace(&model, &left.main);
+            let Counterexample { trace: right, diff } =
+                Counterexample::new(extract_trace(&model, &right.main), &left);
+            DeterminismResult::Nondeterministic { left, right, diff }
+        }
+        SatResult::Unsat => DeterminismResult::Deterministic,
+        SatResult::Unknown => {
+            DeterminismResult::Unknown(solver.get_reason_unknown().unwrap_or_default())
+        }
+    }
+}
@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+
+use p3_air::Air;
+use p3_field::PrimeField64;
+use p3_matrix::dense::RowMajorMatrix;
+use p3_matrix::Matrix;
+use p3_uni_stark::SymbolicExpression;
+use z3::ast::Ast;
+use z3::Context;
+
+use crate::check_unconstrained::{
+    check_preprocessed_height, dispatch_encoding, CheckConfig, CheckError, PublicValues,
+};
+use crate::context::context;
+use crate::field::FieldElement;
+use crate::symbolic::{
+    parse_row_constraints, symbolic_constraints, trace_consts, PublicValueVars, Row, TraceTerms,
+};
+use crate::symbolic_builder::{ExtendedSymbolicAirBuilder, TraceLayout};
+
+/// A constraint that does not vanish on some rows of the honest trace.
+#[derive(Clone, Debug)]
+pub struct ConstraintFailure<F> {
+    pub constraint: usize,
+    /// The rows on which the constraint fails, with its value on each.
+    pub rows: Vec<(usize, F)>,
+    /// The constraint, as printed by `pretty_print`.
+    pub expression: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct EvaluationReport<F> {
+    /// One entry per failing constraint.
+    pub failures: Vec<ConstraintFailure<F>>,
+    /// The `(constraint, row)` instances on which the Z3 encoding of `CheckConfig::encoding`
+    /// evaluates to a different value than the native evaluation, which points at an encoding bug.
+    pub mismatches: Vec<(usize, usize)>,
+}
+
+/// Evaluates every constraint of `air` on every row of the honest trace, listing the ones that do
+/// not vanish. Unlike `p3_uni_stark::check_constraints`, this does not stop at the first failure
+/// and says which constraint failed on which rows. Each value is also computed through the Z3
+/// encoding, as a cross-check on concrete inputs.
+///
+/// The honest trace has no second stage, so the AIR must not have one, and the public values must
+/// be pinned.
+pub fn evaluate_constraints<F, A>(
+    air: &A,
+    main: &RowMajorMatrix<F>,
+    preprocessed: Option<&RowMajorMatrix<F>>,
+    config: &CheckConfig,
+) -> Result<EvaluationReport<F>, CheckError>
+where
+    F: PrimeField64,
+    A: Air<ExtendedSymbolicAirBuilder<F>>,
+{
+    check_preprocessed_height(main.height(), preprocessed)?;
+    if config.permutation_width != 0 || config.num_challenges != 0 {
+        return Err(CheckError::SecondStage);
+    }
+    let PublicValues::Pinned(public_values) = &config.public_values else {
+        return Err(CheckError::UnpinnedPublicValues);
+    };
+
+    let preprocessed_width = preprocessed.map_or(0, |p| p.width());
+    let layout = config.layout(main.width(), preprocessed_width);
+    let constraints = symbolic_constraints(air, layout);
+    let public_values = public_values
+        .iter()
+        .map(|&v| F::from_canonical_u64(v))
+        .collect::<Vec<_>>();
+
+    let public_value_vars = PublicValueVars::from_values(public_values.clone());
+    let terms = TraceTerms {
+        main,
+        preprocessed,
+        permutation: None,
+        public_values: &public_value_vars,
+        challenges: &[],
+    };
+    let values = (0..main.height())
+        .map(|i| {
+            let mut cache = HashMap::new();
+            let row = terms.row(i);
+            constraints
+                .iter()
+                .map(|constraint| evaluate(constraint, row, &mut cache))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let mut failures = vec![];
+    for (constraint, exp) in constraints.iter().enumerate() {
+        let rows = values
+            .iter()
+            .enumerate()
+            .filter(|(_, row_values)| !row_values[constraint].is_zero())
+            .map(|(row, row_values)| (row, row_values[constraint]))
+            .collect::<Vec<_>>();
+        if !rows.is_empty() {
+            failures.push(ConstraintFailure {
+                constraint,
+                rows,
+                expression: pretty_print(exp, layout),
+            });
+        }
+    }
+
+    let ctx = context();
+    let mismatches = dispatch_encoding!(
+        config.encoding,
+        F,
+        cross_check(
+            ctx,
+            &constraints,
+            main,
+            preprocessed,
+            &public_values,
+            &values,
+        )
+    );
+
+    Ok(EvaluationReport {
+        failures,
+        mismatches,
+    })
+}
+
+/// Evaluates an expression on one row of a concrete trace, reusing the values of the nodes in
+/// `cache`.
+fn evaluate<F: PrimeField64>(
+    exp: &SymbolicExpression<F>,
+    row: Row<'_, F>,
+    cache: &mut HashMap<*const SymbolicExpression<F>, F>,
+) -> F {
+    let key = exp as *const _;
+    if let Some(&value) = cache.get(&key) {
+        return value;
+    }
+
+    let mut eval = |exp: &SymbolicExpression<F>| evaluate(exp, row, cache);
+    let value = match exp {
+        SymbolicExpression::Variable(var) => row.variable(var),
+        SymbolicExpression::IsFirstRow => F::from_bool(row.selectors.is_first_row),
+        SymbolicExpression::IsLastRow => F::from_bool(row.selectors.is_last_row),
+        SymbolicExpression::IsTransition => F::from_bool(row.selectors.is_transition),
+        SymbolicExpression::Constant(f) => *f,
+        SymbolicExpression::Add { x, y, .. } => eval(x) + eval(y),
+        SymbolicExpression::Sub { x, y, .. } => eval(x) - eval(y),
+        SymbolicExpression::Neg { x, .. } => -eval(x),
+        SymbolicExpression::Mul { x, y, .. } => eval(x) * eval(y),
+    };
+    cache.insert(key, value);
+    value
+}
+
+/// The instances on which the Z3 encoding disagrees with the native `values`, indexed by row and
+/// then constraint.
+fn cross_check<'ctx, E>(
+    ctx: &'ctx Context,
+    constraints: &[SymbolicExpression<E::F>],
+    main: &RowMajorMatrix<E::F>,
+    preprocessed: Option<&RowMajorMatrix<E::F>>,
+    public_values: &[E::F],
+    values: &[Vec<E::F>],
+) -> Vec<(usize, usize)>
+where
+    E: FieldElement<'ctx>,
+{
+    let main = trace_consts::<E>(ctx, main);
+    let preprocessed = preprocessed.map(|p| trace_consts::<E>(ctx, p));
+    let public_values =
+        PublicValueVars::from_values(public_values.iter().map(|&v| E::from_f(ctx, v)).collect());
+    let terms = TraceTerms {
+        main: &main,
+        preprocessed: preprocessed.as_ref(),
+        permutation: None,
+        public_values: &public_values,
+        challenges: &[],
+    };
+
+    let mut mismatches = vec![];
+    for (row, row_values) in values.iter().enumerate() {
+        let exps = parse_row_constraints(constraints, ctx, terms.row(row));
+        for (constraint, (exp, &value)) in exps.into_iter().zip(row_values).enumerate() {
+            // Compare in the field, as some encodings leave their terms unreduced
+            let agrees = (exp - E::from_f(ctx, value)).is_zero().simplify();
+            if agrees.as_bool() != Some(true) {
+                mismatches.push((constraint, row));
+            }
+        }
+    }
+    mismatches
+}
+
+/// Prints a constraint with its columns named after the part of the trace they belong to, as laid
+/// out in `TraceLayout`. A subexpression shared through `Rc` is printed once, as a `tN` binding
+/// listed after the constraint, so the output stays linear in the number of distinct nodes.
+pub fn pretty_print<F: PrimeField64>(exp: &SymbolicExpression<F>, layout: TraceLayout) -> String {
+    let mut printer = Printer {
+        layout,
+        uses: HashMap::new(),
+        names: HashMap::new(),
+        bindings: vec![],
+    };
+    printer.count_uses(exp);
+    let body = printer.print(exp);
+    if printer.bindings.is_empty() {
+        body
+    } else {
+        format!("{} where {}", body, printer.bindings.join(", "))
+    }
+}
+
+struct Printer<F> {
+    layout: TraceLayout,
+    /// How many parents refer to each node.
+    uses: HashMap<*const SymbolicExpression<F>, usize>,
+    /// The names of the shared nodes printed so far.
+    names: HashMap<*const SymbolicExpression<F>, String>,
+    /// The `tN = ...` bindings of the shared nodes, each after the ones it refers to.
+    bindings: Vec<String>,
+}
+
+impl<F: PrimeField64> Printer<F> {
+    fn count_uses(&mut self, exp: &SymbolicExpression<F>) {
+        let uses = self.uses.entry(exp as *const _).or_insert(0);
+        *uses += 1;
+        if *uses > 1 {
+            return;
+        }
+        match exp {
+            SymbolicExpression::Add { x, y, .. }
+            | SymbolicExpression::Sub { x, y, .. }
+            | SymbolicExpression::Mul { x, y, .. } => {
+                self.count_uses(x);
+                self.count_uses(y);
+            }
+            SymbolicExpression::Neg { x, .. } => self.count_uses(x),
+            _ => {}
+        }
+    }
+
+    fn print(&mut self, exp: &SymbolicExpression<F>) -> String {
+        let key = exp as *const _;
+        if let Some(name) = self.names.get(&key) {
+            return name.clone();
+        }
+
+        let printed = match exp {
+            SymbolicExpression::Variable(var) => {
+                return self.print_variable(var.column, var.is_next)
+            }
+            SymbolicExpression::IsFirstRow => return "is_first_row".to_string(),
+            SymbolicExpression::IsLastRow => return "is_last_row".to_string(),
+            SymbolicExpression::IsTransition => return "is_transition".to_string(),
+            SymbolicExpression::Constant(f) => return f.to_string(),
+            SymbolicExpression::Add { x, y, .. } => {
+                format!("({} + {})", self.print(x), self.print(y))
+            }
+            SymbolicExpression::Sub { x, y, .. } => {
+                format!("({} - {})", self.print(x), self.print(y))
+            }
+            SymbolicExpression::Neg { x, .. } => format!("-{}", self.print(x)),
+            SymbolicExpression::Mul { x, y, .. } => {
+                format!("{} * {}", self.print(x), self.print(y))
+            }
+        };
+        if self.uses[&key] == 1 {
+            return printed;
+        }
+        let name = format!("t{}", self.bindings.len());
+        self.bindings.push(format!("{} = {}", name, printed));
+        self.names.insert(key, name.clone());
+        name
+    }
+
+    fn print_variable(&self, mut column: usize, is_next: bool) -> String {
+        let layout = self.layout;
+        for (local, next, width) in [
+            ("local", "next", layout.width),
+            (
+                "preprocessed_local",
+                "preprocessed_next",
+                layout.preprocessed_width,
+            ),
+            (
+                "permutation_local",
+                "permutation_next",
+                layout.permutation_width,
+            ),
+        ] {
+            if column < width {
+                let row = if is_next { next } else { local };
+                return format!("{}[{}]", row, column);
+            }
+            column -= width;
+        }
+        if column < layout.num_public_values {
+            format!("public_values[{}]", column)
+        } else {
+            format!("challenges[{}]", column - layout.num_public_values)
+        }
+    }
+}
@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+use p3_air::Air;
+use p3_field::PrimeField64;
+use p3_matrix::dense::RowMajorMatrix;
+use p3_matrix::Matrix;
+use p3_uni_stark::SymbolicExpression;
+use z3::Context;
+
+use crate::check_unconstrained::{
+    check_preprocessed_height, dispatch_encoding, uniqueness_query, Challenges, CheckConfig,
+    CheckError, PublicValues,
+};
+use crate::context::context;
+use crate::field::FieldElement;
+use crate::symbolic::{symbolic_constraints, PublicValueVars, Row, TraceTerms};
+use crate::symbolic_builder::ExtendedSymbolicAirBuilder;
+
+/// Writes the query `check_unconstrained` sends to Z3 as an SMT-LIB2 script, to replay it with
+/// other solvers or attach it to a bug report. The script declares every variable, asserts the
+/// constraints and the disjunction that some non-input cell of `T[r][c]` differs from `main`, and
+/// ends with `(check-sat)`. It uses the theory of `CheckConfig::encoding`; the objective and the
+/// blocking of earlier counterexamples are left out.
+pub fn export_smt2<F, A, P>(
+    air: &A,
+    main: &RowMajorMatrix<F>,
+    preprocessed: Option<&RowMajorMatrix<F>>,
+    config: &CheckConfig,
+    path: P,
+) -> io::Result<()>
+where
+    F: PrimeField64,
+    A: Air<ExtendedSymbolicAirBuilder<F>>,
+    P: AsRef<Path>,
+{
+    check_preprocessed_height(main.height(), preprocessed)?;
+    let preprocessed_width = preprocessed.map_or(0, |p| p.width());
+    let constraints = symbolic_constraints(air, config.layout(main.width(), preprocessed_width));
+    let script = dispatch_encoding!(
+        config.encoding,
+        F,
+        smt2_script(context(), &constraints, main, preprocessed, config)
+    );
+    fs::write(path, script)
+}
+
+fn smt2_script<'ctx, E>(
+    ctx: &'ctx Context,
+    constraints: &[SymbolicExpression<E::F>],
+    main: &RowMajorMatrix<E::F>,
+    preprocessed: Option<&RowMajorMatrix<E::F>>,
+    config: &CheckConfig,
+) -> String
+where
+    E: FieldElement<'ctx>,
+{
+    let query = uniqueness_query::<E>(ctx, constraints, main, preprocessed, config);
+    format!("{}(check-sat)\n", query.solver)
+}
+
+/// Writes the constraint system of `check_unconstrained` as an SMT-LIB2 script in the finite field
+/// theory of cvc5, to compare against a solver that reasons about field arithmetic natively. Each
+/// `SymbolicExpression` maps directly to `ff.add`, `ff.mul` and `ff.neg`, with no range
+/// constraints. The theory is quantifier-free, so universal public values and universally
+/// quantified challenges are rejected.
+pub fn export_ff_smt2<F, A, P>(
+    air: &A,
+    main: &RowMajorMatrix<F>,
+    preprocessed: Option<&RowMajorMatrix<F>>,
+    config: &CheckConfig,
+    path: P,
+) -> io::Result<()>
+where
+    F: PrimeField64,
+    A: Air<ExtendedSymbolicAirBuilder<F>>,
+    P: AsRef<Path>,
+{
+    if matches!(config.public_values, PublicValues::Universal(_))
+        || config.challenges == Challenges::ForAll
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "QF_FF cannot express universal public values or challenges",
+        ));
+    }
+    check_preprocessed_height(main.height(), preprocessed)?;
+
+    let width = main.width();
+    let height = main.height();
+    let preprocessed_width = preprocessed.map_or(0, |p| p.width());
+    let constraints = symbolic_constraints(air, config.layout(width, preprocessed_width));
+
+    let mut declarations = vec![];
+    let mut declare = |name: String| {
+        declarations.push(format!("(declare-const {} F)", name));
+        name
+    };
+    let mut names = |name: &str, width: usize| {
+        RowMajorMatrix::new(
+            (0..width * height)
+                .map(|i| declare(format!("|{}[{}][{}]|", name, i / width, i % width)))
+                .collect(),
+            width,
+        )
+    };
+    let vars = names("T", width);
+    let permutation = (config.permutation_width > 0).then(|| names("S", config.permutation_width));
+    let public_values = match &config.public_values {
+        PublicValues::Pinned(values) => values.iter().map(|&v| ff_literal(v)).collect(),
+        PublicValues::Universal(_) => unreachable!(),
+        PublicValues::Free(n) => (0..*n).map(|i| declare(format!("|P[{}]|", i))).collect(),
+    };
+    let challenges = (0..config.num_challenges)
+        .map(|i| declare(format!("|C[{}]|", i)))
+        .collect::<Vec<_>>();
+    let preprocessed = preprocessed.map(|p| {
+        let values = p.values.iter().map(|f| ff_literal(f.as_canonical_u64()));
+        RowMajorMatrix::new(values.collect(), p.width())
+    });
+
+    let public_values = PublicValueVars::from_values(public_values);
+    let terms = TraceTerms {
+        main: &vars,
+        preprocessed: preprocessed.as_ref(),
+        permutation: permutation.as_ref(),
+        public_values: &public_values,
+        challenges: &challenges,
+    };
+    let mut definitions = vec![];
+    let mut assertions = vec![];
+    for i in 0..height {
+        let mut cache = HashMap::new();
+        for constraint in &constraints {
+            let term = ff_term(constraint, terms.row(i), &mut cache, &mut definitions);
+            assertions.push(format!("(assert (= {} {}))", term, ff_literal(0)));
+        }
+    }
+
+    // Pin the inputs, and ask for some other cell to differ from the honest trace
+    let agrees = vars
+        .values
+        .iter()
+        .zip(main.values.iter())
+        .map(|(var, val)| format!("(= {} {})", var, ff_literal(val.as_canonical_u64())));
+    let (pinned, free) = config.split_inputs(width, agrees);
+    for agrees in pinned {
+        assertions.push(format!("(assert {})", agrees));
+    }
+    let differs = free.iter().map(|agrees| format!("(not {})", agrees));
+    assertions.push(format!(
+        "(assert (or false {}))",
+        differs.collect::<Vec<_>>().join(" ")
+    ));
+
+    let script = format!(
+        "(set-logic QF_FF)\n(define-sort F () (_ FiniteField {}))\n{}\n{}\n{}\n(check-sat)\n",
+        F::ORDER_U64,
+        declarations.join("\n"),
+        definitions.join("\n"),
+        assertions.join("\n"),
+    );
+    fs::write(path, script)
+}
+
+/// Runs the `cvc5` binary on the `PATH` on a script written by `export_ff_smt2`, returning its
+/// answer, e.g. `sat` or `unsat`. If `cvc5` exits with an error, the error holds its stderr.
+pub fn run_cvc5<P: AsRef<Path>>(path: P) -> io::Result<String> {
+    let output = Command::new("cvc5").arg(path.as_ref()).output()?;
+    if !output.status.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!(
+                "cvc5 exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// The exports write files, so a trace the check rejects surfaces as invalid input.
+impl From<CheckError> for io::Error {
+    fn from(err: CheckError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, format!("{:?}", err))
+    }
+}
+
+fn ff_literal(value: u64) -> String {
+    format!("(as ff{} F)", value)
+}
+
+/// Prints a constraint evaluated on one row as a term of sort `F`. Each distinct operation node is
+/// defined once, with a `define-fun` pushed to `definitions`, and `cache` maps the nodes already
+/// defined on this row to their names, as in `parse_row_constraints`.
+fn ff_term<F: PrimeField64>(
+    exp: &SymbolicExpression<F>,
+    row: Row<'_, String>,
+    cache: &mut HashMap<*const SymbolicExpression<F>, String>,
+    definitions: &mut Vec<String>,
+) -> String {
+    let key = exp as *const _;
+    if let Some(name) = cache.get(&key) {
+        return name.clone();
+    }
+
+    let mut term = |exp: &SymbolicExpression<F>| ff_term(exp, row, cache, definitions);
+    let body = match exp {
+        SymbolicExpression::Variable(var) => return row.variable(var),
+        SymbolicExpression::IsFirstRow => return ff_literal(row.selectors.is_first_row as u64),
+        SymbolicExpression::IsLastRow => return ff_literal(row.selectors.is_last_row as u64),
+        SymbolicExpression::IsTransition => return ff_literal(row.selectors.is_transition as u64),
+        SymbolicExpression::Constant(f) => return ff_literal(f.as_canonical_u64()),
+        SymbolicExpression::Add { x, y, .. } => format!("(ff.add {} {})", term(x), term(y)),
+        SymbolicExpression::Sub { x, y, .. } => {
+            format!("(ff.add {} (ff.neg {}))", term(x), term(y))
+        }
+        SymbolicExpression::Neg { x, .. } => format!("(ff.neg {})", term(x)),
+        SymbolicExpression::Mul { x, y, .. } => format!("(ff.mul {} {})", term(x), term(y)),
+    };
+    let name = format!("|N[{}]|", definitions.len());
+    definitions.push(format!("(define-fun {} () F {})", name, body));
+    cache.insert(key, name.clone());
+    name
+}
@@ -0,0 +1,316 @@
+use core::fmt;
+use p3_field::extension::{BinomialExtensionField, BinomiallyExtendable};
+use p3_field::{AbstractExtensionField, PrimeField64};
+use std::ops::{Add, AddAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+use z3::{ast::*, *};
+
+use crate::context::context;
+use crate::field::Felt;
+
+/// An element of the binomial extension `F[x] / (x^D - W)`, such as BabyBear's degree-4 extension,
+/// stored as its `D` coefficients over the base field.
+#[derive(Clone, Hash, PartialEq, Eq)]
+pub struct ExtFelt<'ctx, F: PrimeField64, const D: usize>(Vec<Felt<'ctx, F>>);
+
+impl<'ctx, F, const D: usize> ExtFelt<'ctx, F, D>
+where
+    F: PrimeField64 + BinomiallyExtendable<D>,
+{
+    pub fn from_coeffs(coeffs: Vec<Felt<'ctx, F>>) -> Self {
+        assert_eq!(coeffs.len(), D);
+        Self(coeffs)
+    }
+
+    /// Creates one fresh base field variable `{name}[i]` per coefficient.
+    pub fn new_const(solver: &Solver<'ctx>, name: &str) -> Self {
+        Self(
+            (0..D)
+                .map(|i| Felt::new_const(solver, format!("{}[{}]", name, i)))
+                .collect(),
+        )
+    }
+
+    pub fn from_base_slice(ctx: &'ctx Context, coeffs: &[F]) -> Self {
+        Self::from_coeffs(coeffs.iter().map(|&c| Felt::from_f(ctx, c)).collect())
+    }
+
+    pub fn coeffs(&self) -> &[Felt<'ctx, F>] {
+        &self.0
+    }
+
+    pub fn get_ctx(&self) -> &'ctx Context {
+        self.0[0].get_ctx()
+    }
+
+    /// Reads the coefficients, if they are all concrete.
+    pub fn as_u64s(&self) -> Option<Vec<u64>> {
+        self.0.iter().map(Felt::as_u64).collect()
+    }
+
+    pub fn _eq(&self, other: &Self) -> Bool<'ctx> {
+        let eqs = self
+            .0
+            .iter()
+            .zip(other.0.iter())
+            .map(|(a, b)| a._eq(b))
+            .collect::<Vec<_>>();
+        Bool::and(self.get_ctx(), &eqs)
+    }
+
+    pub fn assert_zero(&self, solver: &Solver) {
+        self.0.iter().for_each(|c| c.assert_zero(solver));
+    }
+
+    pub fn assert_ne(&self, solver: &Solver, other: &Self) {
+        solver.assert(&self._eq(other).not());
+    }
+
+    /// Multiplies as polynomials, then folds each `x^(D + k)` into `W * x^k`. Every product is
+    /// reduced, and each coefficient of the result then sums its products in a single `Felt::add`.
+    fn mul_ext(&self, other: &Self) -> Self {
+        let ctx = self.get_ctx();
+        let w = Felt::from_f(ctx, F::w());
+        let mut terms = vec![vec![]; D];
+        for (i, a) in self.0.iter().enumerate() {
+            for (j, b) in other.0.iter().enumerate() {
+                let product = Felt::mul(ctx, &[a, b]);
+                if i + j < D {
+                    terms[i + j].push(product);
+                } else {
+                    terms[i + j - D].push(Felt::mul(ctx, &[&w, &product]));
+                }
+            }
+        }
+        Self(
+            terms
+                .iter()
+                .map(|terms| Felt::add(ctx, &terms[..]))
+                .collect(),
+        )
+    }
+
+    fn zip_with(
+        &self,
+        other: &Self,
+        f: impl Fn(&Felt<'ctx, F>, &Felt<'ctx, F>) -> Felt<'ctx, F>,
+    ) -> Self {
+        Self(
+            self.0
+                .iter()
+                .zip(other.0.iter())
+                .map(|(a, b)| f(a, b))
+                .collect(),
+        )
+    }
+}
+
+/// Multiplies `a` and `b` as `ExtFelt`s and checks that the product is the only solution and
+/// matches Plonky3's, as a sanity check of `mul_ext` before it is trusted on symbolic values.
+pub fn cross_check_mul<F, const D: usize>(
+    a: BinomialExtensionField<F, D>,
+    b: BinomialExtensionField<F, D>,
+) -> bool
+where
+    F: PrimeField64 + BinomiallyExtendable<D>,
+{
+    let ctx = context();
+    let solver = Solver::new(ctx);
+    let product = ExtFelt::<F, D>::from_base_slice(ctx, a.as_base_slice())
+        * ExtFelt::from_base_slice(ctx, b.as_base_slice());
+    let x = ExtFelt::<F, D>::new_const(&solver, "X");
+    (&x - &product).assert_zero(&solver);
+    if solver.check() != SatResult::Sat {
+        return false;
+    }
+
+    let model = solver.get_model().unwrap();
+    let found = ExtFelt::from_coeffs(
+        x.coeffs()
+            .iter()
+            .map(|c| model.eval(c, true).unwrap())
+            .collect(),
+    );
+    let expected = (a * b)
+        .as_base_slice()
+        .iter()
+        .map(|c| c.as_canonical_u64())
+        .collect();
+    if found.as_u64s() != Some(expected) {
+        return false;
+    }
+
+    // No other assignment to `X` may satisfy the product
+    x.assert_ne(&solver, &found);
+    solver.check() == SatResult::Unsat
+}
+
+impl<'ctx, F: PrimeField64, const D: usize> fmt::Debug for ExtFelt<'ctx, F, D> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.debug_list().entries(self.0.iter()).finish()
+    }
+}
+
+impl<'ctx, F: PrimeField64, const D: usize> fmt::Display for ExtFelt<'ctx, F, D> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        <Self as fmt::Debug>::fmt(self, f)
+    }
+}
+
+impl<'ctx, F, const D: usize> Add<ExtFelt<'ctx, F, D>> for ExtFelt<'ctx, F, D>
+where
+    F: PrimeField64 + BinomiallyExtendable<D>,
+{
+    type Output = ExtFelt<'ctx, F, D>;
+
+    fn add(self, other: ExtFelt<'ctx, F, D>) -> Self::Output {
+        &self + &other
+    }
+}
+
+impl<'ctx, F, const D: usize> Add<&ExtFelt<'ctx, F, D>> for &ExtFelt<'ctx, F, D>
+where
+    F: PrimeField64 + BinomiallyExtendable<D>,
+{
+    type Output = ExtFelt<'ctx, F, D>;
+
+    fn add(self, other: &ExtFelt<'ctx, F, D>) -> Self::Output {
+        self.zip_with(other, |a, b| a + b)
+    }
+}
+
+impl<'ctx, F, const D: usize> AddAssign<ExtFelt<'ctx, F, D>> for ExtFelt<'ctx, F, D>
+where
+    F: PrimeField64 + BinomiallyExtendable<D>,
+{
+    fn add_assign(&mut self, other: ExtFelt<'ctx, F, D>) {
+        *self = &*self + &other
+    }
+}
+
+impl<'ctx, F, const D: usize> Sub<ExtFelt<'ctx, F, D>> for ExtFelt<'ctx, F, D>
+where
+    F: PrimeField64 + BinomiallyExtendable<D>,
+{
+    type Output = ExtFelt<'ctx, F, D>;
+
+    fn sub(self, other: ExtFelt<'ctx, F, D>) -> Self::Output {
+        &self - &other
+    }
+}
+
+impl<'ctx, F, const D: usize> Sub<&ExtFelt<'ctx, F, D>> for &ExtFelt<'ctx, F, D>
+where
+    F: PrimeField64 + BinomiallyExtendable<D>,
+{
+    type Output = ExtFelt<'ctx, F, D>;
+
+    fn sub(self, other: &ExtFelt<'ctx, F, D>) -> Self::Output {
+        self.zip_with(other, |a, b| a - b)
+    }
+}
+
+impl<'ctx, F, const D: usize> SubAssign<ExtFelt<'ctx, F, D>> for ExtFelt<'ctx, F, D>
+where
+    F: PrimeField64 + BinomiallyExtendable<D>,
+{
+    fn sub_assign(&mut self, other: ExtFelt<'ctx, F, D>) {
+        *self = &*self - &other
+    }
+}
+
+impl<'ctx, F, const D: usize> Mul<ExtFelt<'ctx, F, D>> for ExtFelt<'ctx, F, D>
+where
+    F: PrimeField64 + BinomiallyExtendable<D>,
+{
+    type Output = ExtFelt<'ctx, F, D>;
+
+    fn mul(self, other: ExtFelt<'ctx, F, D>) -> Self::Output {
+        self.mul_ext(&other)
+    }
+}
+
+impl<'ctx, F, const D: usize> Mul<&ExtFelt<'ctx, F, D>> for &ExtFelt<'ctx, F, D>
+where
+    F: PrimeField64 + BinomiallyExtendable<D>,
+{
+    type Output = ExtFelt<'ctx, F, D>;
+
+    fn mul(self, other: &ExtFelt<'ctx, F, D>) -> Self::Output {
+        self.mul_ext(other)
+    }
+}
+
+impl<'ctx, F, const D: usize> MulAssign<ExtFelt<'ctx, F, D>> for ExtFelt<'ctx, F, D>
+where
+    F: PrimeField64 + BinomiallyExtendable<D>,
+{
+    fn mul_assign(&mut self, other: ExtFelt<'ctx, F, D>) {
+        *self = self.mul_ext(&other)
+    }
+}
+
+/// Scales every coefficient by a base field element.
+impl<'ctx, F, const D: usize> Mul<&Felt<'ctx, F>> for &ExtFelt<'ctx, F, D>
+where
+    F: PrimeField64 + BinomiallyExtendable<D>,
+{
+    type Output = ExtFelt<'ctx, F, D>;
+
+    fn mul(self, other: &Felt<'ctx, F>) -> Self::Output {
+        ExtFelt(self.0.iter().map(|a| a * other).collect())
+    }
+}
+
+impl<'ctx, F, const D: usize> Neg for ExtFelt<'ctx, F, D>
+where
+    F: PrimeField64 + BinomiallyExtendable<D>,
+{
+    type Output = ExtFelt<'ctx, F, D>;
+
+    fn neg(self) -> Self::Output {
+        ExtFelt(self.0.into_iter().map(Neg::neg).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use p3_baby_bear::BabyBear;
+    use p3_field::AbstractField;
+    use rand::random;
+
+    use super::*;
+
+    type Ext = BinomialExtensionField<BabyBear, 4>;
+
+    #[test]
+    fn mul_matches_plonky3_on_random_values() {
+        for _ in 0..20 {
+            let (a, b): (Ext, Ext) = (random(), random());
+            assert!(cross_check_mul(a, b), "{:?} * {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn mul_matches_plonky3_near_zero_and_p() {
+        let p_minus_one = BabyBear::neg_one();
+        let edges = [
+            Ext::zero(),
+            Ext::one(),
+            Ext::neg_one(),
+            Ext::from_base_slice(&[p_minus_one; 4]),
+            Ext::from_base_slice(&[BabyBear::zero(), p_minus_one, BabyBear::one(), p_minus_one]),
+            // The element `x`, whose powers fold through `W`
+            Ext::from_base_slice(&[
+                BabyBear::zero(),
+                BabyBear::one(),
+                BabyBear::zero(),
+                BabyBear::zero(),
+            ]),
+        ];
+        for &a in &edges {
+            for &b in &edges {
+                assert!(cross_check_mul(a, b), "{:?} * {:?}", a, b);
+            }
+        }
+    }
+}
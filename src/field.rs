@@ -1,14 +1,64 @@
 use core::fmt;
-use p3_field::PrimeField64;
+use p3_field::{AbstractField, PrimeField64};
 use std::{
     borrow::Borrow,
     hash::Hash,
+    iter::{Product, Sum},
     marker::PhantomData,
     ops::{Add, AddAssign, Mul, MulAssign, Neg, Sub, SubAssign},
 };
 use z3::{ast::*, *};
 use z3_sys::*;
 
+use crate::context::context;
+
+/// A Z3 encoding of the elements of a prime field. Checks are generic over this and pick an
+/// implementation from `CheckConfig::encoding`.
+pub trait FieldElement<'ctx>:
+    Ast<'ctx>
+    + Clone
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Neg<Output = Self>
+{
+    type F: PrimeField64;
+
+    /// A fresh variable, constrained to a canonical value.
+    fn new_const(solver: &Solver<'ctx>, name: String) -> Self;
+
+    /// A fresh variable without a range constraint, to be bound by a quantifier.
+    fn new_unbounded(ctx: &'ctx Context, name: String) -> Self;
+
+    fn from_u64(ctx: &'ctx Context, u: u64) -> Self;
+
+    fn from_bool(ctx: &'ctx Context, b: bool) -> Self {
+        Self::from_u64(ctx, b as u64)
+    }
+
+    fn from_f(ctx: &'ctx Context, f: Self::F) -> Self {
+        Self::from_u64(ctx, f.as_canonical_u64())
+    }
+
+    fn as_u64(&self) -> Option<u64>;
+
+    /// Whether the underlying term lies in `[0, p)`.
+    fn is_canonical(&self) -> Bool<'ctx>;
+
+    /// Whether the term is zero in the field.
+    fn is_zero(&self) -> Bool<'ctx> {
+        self._eq(&Self::from_u64(self.get_ctx(), 0))
+    }
+
+    fn assert_zero(&self, solver: &Solver<'ctx>) {
+        solver.assert(&self.is_zero());
+    }
+
+    fn assert_eq(&self, solver: &Solver<'ctx>, other: &Self) {
+        solver.assert(&self._eq(other));
+    }
+}
+
 #[derive(Clone, Hash, PartialEq, Eq)]
 pub struct Felt<'ctx, F: PrimeField64>(Int<'ctx>, PhantomData<F>);
 
@@ -17,16 +67,19 @@ impl<'ctx, F: PrimeField64> Felt<'ctx, F> {
         Self(x, PhantomData::<F>)
     }
 
-    pub fn new_const<S: Into<Symbol>>(solver: &'ctx Solver, name: S) -> Self {
+    pub fn new_const<S: Into<Symbol>>(solver: &Solver<'ctx>, name: S) -> Self {
         let ctx = solver.get_context();
         let x = Self::from_int(Int::new_const(ctx, name));
+        solver.assert(&x.is_canonical());
+        x
+    }
 
+    /// Whether the underlying integer lies in `[0, p)`.
+    pub fn is_canonical(&self) -> Bool<'ctx> {
+        let ctx = self.get_ctx();
         let zero = Int::from_u64(ctx, 0);
         let p = Int::from_u64(ctx, F::ORDER_U64);
-        solver.assert(&x.0.ge(&zero));
-        solver.assert(&x.0.lt(&p));
-
-        x
+        Bool::and(ctx, &[self.0.ge(&zero), self.0.lt(&p)])
     }
 
     pub fn from_u64(ctx: &'ctx Context, u: u64) -> Self {
@@ -101,6 +154,30 @@ impl<'ctx, F: PrimeField64> Felt<'ctx, F> {
     }
 }
 
+impl<'ctx, F: PrimeField64> FieldElement<'ctx> for Felt<'ctx, F> {
+    type F = F;
+
+    fn new_const(solver: &Solver<'ctx>, name: String) -> Self {
+        Felt::new_const(solver, name)
+    }
+
+    fn new_unbounded(ctx: &'ctx Context, name: String) -> Self {
+        Felt::from_int(Int::new_const(ctx, name))
+    }
+
+    fn from_u64(ctx: &'ctx Context, u: u64) -> Self {
+        Felt::from_u64(ctx, u)
+    }
+
+    fn as_u64(&self) -> Option<u64> {
+        Felt::as_u64(self)
+    }
+
+    fn is_canonical(&self) -> Bool<'ctx> {
+        Felt::is_canonical(self)
+    }
+}
+
 impl<'ctx, F: PrimeField64> Ast<'ctx> for Felt<'ctx, F> {
     unsafe fn wrap(ctx: &'ctx Context, ast: Z3_ast) -> Self {
         Self::from_int(Int::wrap(ctx, ast))
@@ -227,3 +304,252 @@ impl<'ctx, F: PrimeField64> Neg for Felt<'ctx, F> {
         self.unary_minus()
     }
 }
+
+impl<'ctx, F: PrimeField64> From<&Felt<'ctx, F>> for Felt<'ctx, F> {
+    fn from(x: &Felt<'ctx, F>) -> Self {
+        x.clone()
+    }
+}
+
+impl<'ctx, F: PrimeField64> Add<&Felt<'ctx, F>> for Felt<'ctx, F> {
+    type Output = Felt<'ctx, F>;
+
+    fn add(self, other: &Felt<'ctx, F>) -> Self::Output {
+        &self + other
+    }
+}
+
+impl<'ctx, F: PrimeField64> Add<Felt<'ctx, F>> for &Felt<'ctx, F> {
+    type Output = Felt<'ctx, F>;
+
+    fn add(self, other: Felt<'ctx, F>) -> Self::Output {
+        self + &other
+    }
+}
+
+impl<'ctx, F: PrimeField64> Add<F> for Felt<'ctx, F> {
+    type Output = Felt<'ctx, F>;
+
+    fn add(self, other: F) -> Self::Output {
+        &self + other
+    }
+}
+
+impl<'ctx, F: PrimeField64> Add<F> for &Felt<'ctx, F> {
+    type Output = Felt<'ctx, F>;
+
+    fn add(self, other: F) -> Self::Output {
+        self + &Felt::from_f(self.get_ctx(), other)
+    }
+}
+
+impl<'ctx, F: PrimeField64> Sub<&Felt<'ctx, F>> for Felt<'ctx, F> {
+    type Output = Felt<'ctx, F>;
+
+    fn sub(self, other: &Felt<'ctx, F>) -> Self::Output {
+        &self - other
+    }
+}
+
+impl<'ctx, F: PrimeField64> Sub<Felt<'ctx, F>> for &Felt<'ctx, F> {
+    type Output = Felt<'ctx, F>;
+
+    fn sub(self, other: Felt<'ctx, F>) -> Self::Output {
+        self - &other
+    }
+}
+
+impl<'ctx, F: PrimeField64> Sub<F> for Felt<'ctx, F> {
+    type Output = Felt<'ctx, F>;
+
+    fn sub(self, other: F) -> Self::Output {
+        &self - other
+    }
+}
+
+impl<'ctx, F: PrimeField64> Sub<F> for &Felt<'ctx, F> {
+    type Output = Felt<'ctx, F>;
+
+    fn sub(self, other: F) -> Self::Output {
+        self - &Felt::from_f(self.get_ctx(), other)
+    }
+}
+
+impl<'ctx, F: PrimeField64> Mul<&Felt<'ctx, F>> for Felt<'ctx, F> {
+    type Output = Felt<'ctx, F>;
+
+    fn mul(self, other: &Felt<'ctx, F>) -> Self::Output {
+        &self * other
+    }
+}
+
+impl<'ctx, F: PrimeField64> Mul<Felt<'ctx, F>> for &Felt<'ctx, F> {
+    type Output = Felt<'ctx, F>;
+
+    fn mul(self, other: Felt<'ctx, F>) -> Self::Output {
+        self * &other
+    }
+}
+
+impl<'ctx, F: PrimeField64> Mul<F> for Felt<'ctx, F> {
+    type Output = Felt<'ctx, F>;
+
+    fn mul(self, other: F) -> Self::Output {
+        &self * other
+    }
+}
+
+impl<'ctx, F: PrimeField64> Mul<F> for &Felt<'ctx, F> {
+    type Output = Felt<'ctx, F>;
+
+    fn mul(self, other: F) -> Self::Output {
+        self * &Felt::from_f(self.get_ctx(), other)
+    }
+}
+
+// `AbstractField` constructors take no context, so they build terms in the global one.
+impl<F: PrimeField64> Default for Felt<'static, F> {
+    fn default() -> Self {
+        Self::zero()
+    }
+}
+
+impl<F: PrimeField64> Sum for Felt<'static, F> {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::zero(), |acc, x| acc + x)
+    }
+}
+
+impl<F: PrimeField64> Product for Felt<'static, F> {
+    fn product<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::one(), |acc, x| acc * x)
+    }
+}
+
+impl<F: PrimeField64> AbstractField for Felt<'static, F> {
+    type F = F;
+
+    fn zero() -> Self {
+        Felt::from_f(context(), F::zero())
+    }
+
+    fn one() -> Self {
+        Felt::from_f(context(), F::one())
+    }
+
+    fn two() -> Self {
+        Felt::from_f(context(), F::two())
+    }
+
+    fn neg_one() -> Self {
+        Felt::from_f(context(), F::neg_one())
+    }
+
+    fn from_f(f: Self::F) -> Self {
+        Felt::from_f(context(), f)
+    }
+
+    fn from_bool(b: bool) -> Self {
+        Felt::from_bool(context(), b)
+    }
+
+    fn from_canonical_u8(n: u8) -> Self {
+        Felt::from_f(context(), F::from_canonical_u8(n))
+    }
+
+    fn from_canonical_u16(n: u16) -> Self {
+        Felt::from_f(context(), F::from_canonical_u16(n))
+    }
+
+    fn from_canonical_u32(n: u32) -> Self {
+        Felt::from_f(context(), F::from_canonical_u32(n))
+    }
+
+    fn from_canonical_u64(n: u64) -> Self {
+        Felt::from_f(context(), F::from_canonical_u64(n))
+    }
+
+    fn from_canonical_usize(n: usize) -> Self {
+        Felt::from_f(context(), F::from_canonical_usize(n))
+    }
+
+    fn from_wrapped_u32(n: u32) -> Self {
+        Felt::from_f(context(), F::from_wrapped_u32(n))
+    }
+
+    fn from_wrapped_u64(n: u64) -> Self {
+        Felt::from_f(context(), F::from_wrapped_u64(n))
+    }
+
+    fn generator() -> Self {
+        Felt::from_f(context(), F::generator())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use p3_baby_bear::BabyBear;
+    use rand::random;
+
+    use super::*;
+
+    /// Zero, one, two, values next to `p`, and a few random canonical values.
+    fn operands<F: PrimeField64>() -> Vec<F> {
+        let p = F::ORDER_U64;
+        let mut values = [0, 1, 2, p - 2, p - 1].map(F::from_canonical_u64).to_vec();
+        values.extend((0..4).map(|_| F::from_canonical_u64(random::<u64>() % p)));
+        values
+    }
+
+    /// Asserts that `term` equals `expected` in the field and differs from `expected + 1`, both
+    /// through `assert_zero` and through `is_zero`, which some encodings implement differently.
+    fn assert_value<'ctx, E: FieldElement<'ctx>>(term: E, expected: E::F, what: &str) {
+        let ctx = term.get_ctx();
+        let cases = [
+            (expected, SatResult::Sat),
+            (expected + E::F::one(), SatResult::Unsat),
+        ];
+        for (value, answer) in cases {
+            let diff = term.clone() - E::from_f(ctx, value);
+            let solver = Solver::new(ctx);
+            diff.assert_zero(&solver);
+            assert_eq!(solver.check(), answer, "assert_zero({} - {})", what, value);
+            let solver = Solver::new(ctx);
+            solver.assert(&diff.is_zero());
+            assert_eq!(solver.check(), answer, "is_zero({} - {})", what, value);
+        }
+    }
+
+    /// Cross-checks the arithmetic of an encoding against native field arithmetic on concrete
+    /// operands, including nested terms and repeated squaring, which widen the tracked ranges.
+    pub(crate) fn cross_check_arithmetic<'ctx, E: FieldElement<'ctx>>(ctx: &'ctx Context) {
+        let values = operands::<E::F>();
+        let lift = |x| E::from_f(ctx, x);
+        for &a in &values {
+            assert_value(-lift(a), -a, &format!("-{}", a));
+
+            let (mut term, mut power) = (lift(a), a);
+            for _ in 0..3 {
+                term = term.clone() * term;
+                power = power * power;
+            }
+            assert_value(term, power, &format!("{}^8", a));
+
+            for &b in &values {
+                assert_value(lift(a) + lift(b), a + b, &format!("{} + {}", a, b));
+                assert_value(lift(a) - lift(b), a - b, &format!("{} - {}", a, b));
+                assert_value(lift(a) * lift(b), a * b, &format!("{} * {}", a, b));
+                assert_value(
+                    (lift(a) - lift(b)) * (lift(a) + lift(b)) - -lift(b),
+                    (a - b) * (a + b) + b,
+                    &format!("({0} - {1}) * ({0} + {1}) + {1}", a, b),
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn felt_matches_native_arithmetic() {
+        cross_check_arithmetic::<Felt<BabyBear>>(context());
+    }
+}
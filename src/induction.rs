@@ -0,0 +1,184 @@
+use p3_air::Air;
+use p3_field::PrimeField64;
+use p3_matrix::dense::RowMajorMatrix;
+use p3_uni_stark::SymbolicExpression;
+use z3::{Context, SatResult};
+
+use crate::check_unconstrained::{dispatch_encoding, CheckConfig};
+use crate::context::context;
+use crate::field::FieldElement;
+use crate::symbolic::{
+    assert_row_constraints, cells_agree, extract_trace, pin_and_differ, split_cells,
+    symbolic_constraints, Row, Selectors, SharedTerms, TraceVars,
+};
+use crate::symbolic_builder::ExtendedSymbolicAirBuilder;
+
+/// The outcome of a k-induction proof that traces of every height are determined by their inputs.
+#[derive(Clone, Debug)]
+pub enum InductionResult<F> {
+    /// The first `k + 1` rows are determined, and any `k` consecutive rows determine the row after
+    /// them, so every trace of height greater than `k` is determined.
+    Proved { k: usize },
+    /// Two different first `k + 1` rows that agree on the inputs, for the largest `k` whose step
+    /// held. Constraints on later rows are not considered, so this may not extend to a full trace.
+    BaseCaseFailed {
+        k: usize,
+        left: RowMajorMatrix<F>,
+        right: RowMajorMatrix<F>,
+    },
+    /// The inductive step failed for every `k` up to `max_k`.
+    Inconclusive { max_k: usize },
+    /// The solver gave up; holds `Solver::get_reason_unknown`.
+    Unknown(String),
+}
+
+/// Tries to prove, for increasing `k` up to `max_k`, that traces of any height are determined by
+/// their inputs. Only `Input::Column` inputs are meaningful here, as the inductive step does not
+/// know which rows it is looking at.
+pub fn prove_by_induction<F, A>(air: &A, max_k: usize, config: &CheckConfig) -> InductionResult<F>
+where
+    F: PrimeField64,
+    A: Air<ExtendedSymbolicAirBuilder<F>>,
+{
+    let ctx = context();
+    let width = air.width();
+    // Preprocessed columns are tied to a fixed height, so AIRs reading them are not supported
+    let constraints = symbolic_constraints(air, config.layout(width, 0));
+
+    let check = |k, case| {
+        dispatch_encoding!(
+            config.encoding,
+            F,
+            check_case(ctx, &constraints, width, k, case, config)
+        )
+    };
+
+    // The step at `k` implies the step at every larger `k`, but the base may only hold for a
+    // larger `k`, so a failed base is only reported once `max_k` is reached.
+    let mut step_holds = false;
+    let mut base_failure = None;
+    for k in 1..=max_k {
+        if !step_holds {
+            match check(k, Case::Step) {
+                Outcome::Holds => step_holds = true,
+                Outcome::Fails(..) => continue,
+                Outcome::Unknown(reason) => return InductionResult::Unknown(reason),
+            }
+        }
+
+        match check(k, Case::Base) {
+            Outcome::Holds => return InductionResult::Proved { k },
+            Outcome::Fails(left, right) => base_failure = Some((k, left, right)),
+            Outcome::Unknown(reason) => return InductionResult::Unknown(reason),
+        }
+    }
+
+    match base_failure {
+        Some((k, left, right)) => InductionResult::BaseCaseFailed { k, left, right },
+        None => InductionResult::Inconclusive { max_k },
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Case {
+    /// The first `k + 1` rows of the trace.
+    Base,
+    /// `k + 1` consecutive rows starting after the first row and ending at most at the last row.
+    Step,
+}
+
+enum Outcome<F> {
+    Holds,
+    Fails(RowMajorMatrix<F>, RowMajorMatrix<F>),
+    Unknown(String),
+}
+
+/// Builds two copies of `k + 1` consecutive rows satisfying the constraints on every row, and asks
+/// whether they can differ. In the base case they may differ anywhere; in the step they agree on
+/// their first `k` rows and may differ only on the last. The last row may or may not be the last
+/// of the trace, so the case must hold both ways.
+fn check_case<'ctx, E>(
+    ctx: &'ctx Context,
+    constraints: &[SymbolicExpression<E::F>],
+    width: usize,
+    k: usize,
+    case: Case,
+    config: &CheckConfig,
+) -> Outcome<E::F>
+where
+    E: FieldElement<'ctx>,
+{
+    for is_last_row in [false, true] {
+        match check_window::<E>(ctx, constraints, width, k, case, is_last_row, config) {
+            Outcome::Holds => {}
+            outcome => return outcome,
+        }
+    }
+    Outcome::Holds
+}
+
+/// `check_case` with the last row of the window being the last of the trace or not. Either way,
+/// its constraints reach a fresh row `k + 1` that is left unconstrained in both copies, as the
+/// successor, or the first row the last one wraps around to, lies outside the window.
+fn check_window<'ctx, E>(
+    ctx: &'ctx Context,
+    constraints: &[SymbolicExpression<E::F>],
+    width: usize,
+    k: usize,
+    case: Case,
+    is_last_row: bool,
+    config: &CheckConfig,
+) -> Outcome<E::F>
+where
+    E: FieldElement<'ctx>,
+{
+    let solver = config.solver(ctx);
+
+    // Preprocessed columns are not supported, see `prove_by_induction`
+    let shared = SharedTerms::<E>::with_preprocessed(&solver, None, config, false);
+    let left = TraceVars::<E>::new(&solver, ("A", "SA"), width, k + 2, config, false);
+    let right = TraceVars::<E>::new(&solver, ("B", "SB"), width, k + 2, config, false);
+    for vars in [&left, &right] {
+        let terms = shared.terms(vars);
+        for row in 0..=k {
+            // Only the last row of the window can be the last of the trace.
+            let is_last_row = is_last_row && row == k;
+            let row = Row {
+                selectors: Selectors {
+                    is_first_row: case == Case::Base && row == 0,
+                    is_last_row,
+                    is_transition: !is_last_row,
+                },
+                ..terms.row(row)
+            };
+            assert_row_constraints(&solver, constraints, row);
+        }
+    }
+
+    // Row `k + 1` is outside the window, and is not compared
+    let window = (k + 1) * width;
+    let agrees = cells_agree(&left.main, &right.main);
+    let shared_cells = split_cells(
+        width,
+        agrees.into_iter().take(window),
+        |row, col| match case {
+            Case::Base => config.is_input(row, col),
+            Case::Step => config.is_input_column(col) || row < k,
+        },
+    );
+    pin_and_differ(&solver, shared_cells);
+
+    match solver.check() {
+        SatResult::Sat => {
+            let model = solver.get_model().unwrap();
+            let extract = |vars| {
+                let mut trace = extract_trace(&model, vars);
+                trace.values.truncate(window);
+                trace
+            };
+            Outcome::Fails(extract(&left.main), extract(&right.main))
+        }
+        SatResult::Unsat => Outcome::Holds,
+        SatResult::Unknown => Outcome::Unknown(solver.get_reason_unknown().unwrap_or_default()),
+    }
+}
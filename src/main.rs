@@ -1,30 +1,258 @@
 extern crate alloc;
 
+mod air;
+mod bitvec;
 mod check_unconstrained;
 mod context;
+mod determinism;
+mod evaluate;
+mod export;
+mod extension;
 mod field;
+mod induction;
 mod keccak_air;
+mod minimize;
+mod quotient;
+mod redundancy;
+mod report;
 mod round_flags_air;
+mod symbolic;
+mod symbolic_builder;
+mod window;
+mod z3_builder;
 
-// use keccak_air::{generate_trace_rows, KeccakAir};
+use keccak_air::KeccakAir;
 use p3_baby_bear::BabyBear;
+use p3_field::extension::BinomialExtensionField;
+use p3_matrix::Matrix;
 use p3_uni_stark::check_constraints;
-// use rand::random;
+use rand::random;
 use round_flags_air::{generate_trace_rows, RoundFlagsAir};
+use std::env;
+use z3::Solver;
 
-use crate::check_unconstrained::check_unconstrained;
+use crate::check_unconstrained::{
+    check_unconstrained_validated, compare_encodings, CheckConfig, CheckResult, Counterexample,
+    EncodingComparison,
+};
+use crate::context::context;
+use crate::determinism::{
+    check_deterministic, determinism_map, explain_cell, CellExplanation, CellStatus,
+    DeterminismResult,
+};
+use crate::evaluate::evaluate_constraints;
+use crate::export::{export_ff_smt2, export_smt2, run_cvc5};
+use crate::extension::cross_check_mul;
+use crate::field::Felt;
+use crate::induction::prove_by_induction;
+use crate::minimize::{minimal_constraint_subset, Property};
+use crate::redundancy::{find_redundant_constraints, Redundancy, Scope};
+use crate::report::constraint_report;
+use crate::symbolic::new_trace_vars;
+use crate::window::{check_windows, Boundary};
+use crate::z3_builder::assert_air_constraints;
 
-// const NUM_HASHES: usize = 1;
+const NUM_HASHES: usize = 1;
 
 fn main() {
     type Val = BabyBear;
 
-    // let inputs = (0..NUM_HASHES).map(|_| random()).collect::<Vec<_>>();
     let trace = generate_trace_rows::<Val>();
 
+    let config = CheckConfig::default();
+
+    let layout = config.layout(trace.width(), 0);
+    for (i, stats) in constraint_report(&RoundFlagsAir {}, layout, 3)
+        .iter()
+        .enumerate()
+    {
+        println!(
+            "Constraint {}: degree {}, {} nodes, columns {:?}, {:?} rows{}",
+            i,
+            stats.degree,
+            stats.nodes,
+            stats.columns,
+            stats.rows,
+            if stats.exceeds_max_degree {
+                " (exceeds max degree)"
+            } else {
+                ""
+            }
+        );
+    }
+
+    // Say which constraints fail on which rows, before `check_constraints` panics on the first one
+    let report = evaluate_constraints(&RoundFlagsAir {}, &trace, None, &config)
+        .expect("pinned single stage");
+    for failure in &report.failures {
+        println!(
+            "Constraint {} fails on (row, value) {:?}: {}",
+            failure.constraint, failure.rows, failure.expression
+        );
+    }
+    if !report.mismatches.is_empty() {
+        println!("Encoding bug: Z3 disagrees on {:?}", report.mismatches);
+    }
+
     check_constraints(&RoundFlagsAir {}, &trace);
-    check_unconstrained(&RoundFlagsAir {}, &trace)
 
-    // check_constraints(&KeccakAir {}, &trace);
-    // check_unconstrained(&KeccakAir {}, &trace)
+    // The honest trace must also satisfy the AIR evaluated directly on Z3 terms
+    let solver = Solver::new(context());
+    let vars = new_trace_vars::<Felt<Val>>(&solver, "T", trace.width(), trace.height());
+    assert_air_constraints(&solver, &RoundFlagsAir {}, &vars);
+    for (var, &value) in vars.values.iter().zip(trace.values.iter()) {
+        var.assert_eq(&solver, &Felt::from_f(context(), value));
+    }
+    println!("Direct encoding of the honest trace: {:?}", solver.check());
+
+    // The Z3 extension field multiplication must match Plonky3's before any check relies on it
+    let (a, b): (BinomialExtensionField<Val, 4>, _) = (random(), random());
+    println!(
+        "Extension multiplication agrees with Plonky3: {}",
+        cross_check_mul(a, b)
+    );
+
+    let result = check_unconstrained_validated(&RoundFlagsAir {}, &trace, &config)
+        .expect("no preprocessed trace");
+
+    let print_counterexamples = |counterexamples: &[Counterexample<Val>]| {
+        for counterexample in counterexamples {
+            for row in counterexample
+                .trace
+                .values
+                .chunks(counterexample.trace.width())
+            {
+                for value in row {
+                    print!("{} ", value);
+                }
+                println!()
+            }
+            println!("Differs at {:?}", counterexample.diff);
+        }
+    };
+    match result {
+        CheckResult::Constrained => println!("No solution"),
+        CheckResult::Underconstrained(counterexamples) => print_counterexamples(&counterexamples),
+        CheckResult::Unknown(reason) => println!("Unknown: {}", reason),
+        CheckResult::EncodingBug { accepted, rejected } => {
+            print_counterexamples(&accepted);
+            println!("Encoding bug: Plonky3 rejects these counterexamples");
+            print_counterexamples(&rejected);
+        }
+    }
+
+    let print_comparison = |comparison: EncodingComparison<Val>| {
+        for run in &comparison.runs {
+            println!("{:?} encoding took {:?}", run.encoding, run.elapsed);
+        }
+        match comparison.disagreement {
+            Some((a, b)) => println!("Encoding bug: {:?} and {:?} encodings disagree", a, b),
+            None => println!("All encodings agree"),
+        }
+    };
+    let comparison =
+        compare_encodings(&RoundFlagsAir {}, &trace, None, &config).expect("no preprocessed trace");
+    print_comparison(comparison);
+
+    // The full Keccak AIR takes far longer than the rest, so benchmarking it is opt-in
+    if env::var_os("BENCH_KECCAK").is_some() {
+        let inputs = (0..NUM_HASHES).map(|_| random()).collect::<Vec<_>>();
+        let keccak_trace = keccak_air::generate_trace_rows::<Val>(inputs);
+        check_constraints(&KeccakAir {}, &keccak_trace);
+        let comparison = compare_encodings(&KeccakAir {}, &keccak_trace, None, &config)
+            .expect("no preprocessed trace");
+        print_comparison(comparison);
+    }
+
+    // Writing the queries to disk, and running cvc5 on the QF_FF one, are opt-in
+    if env::var_os("EXPORT_SMT2").is_some() {
+        export_smt2(&RoundFlagsAir {}, &trace, None, &config, "round_flags.smt2")
+            .expect("failed to write the SMT-LIB2 query");
+        export_ff_smt2(
+            &RoundFlagsAir {},
+            &trace,
+            None,
+            &config,
+            "round_flags_ff.smt2",
+        )
+        .expect("failed to write the QF_FF query");
+        if env::var_os("RUN_CVC5").is_some() {
+            match run_cvc5("round_flags_ff.smt2") {
+                Ok(answer) => println!("cvc5: {}", answer),
+                Err(err) => println!("cvc5 failed: {}", err),
+            }
+        }
+    }
+
+    let map =
+        determinism_map(&RoundFlagsAir {}, &trace, None, &config).expect("no preprocessed trace");
+    for row in map.values.chunks(map.width()) {
+        for status in row {
+            let c = match status {
+                CellStatus::Determined => '.',
+                CellStatus::Free => 'X',
+                CellStatus::Unknown => '?',
+                CellStatus::Input => 'I',
+            };
+            print!("{}", c);
+        }
+        println!()
+    }
+
+    if let Some(i) = map.values.iter().position(|s| *s == CellStatus::Determined) {
+        let cell = (i / map.width(), i % map.width());
+        if let CellExplanation::Determined(core) =
+            explain_cell(&RoundFlagsAir {}, &trace, None, cell, &config)
+                .expect("no preprocessed trace")
+        {
+            println!("Cell {:?} is forced by (constraint, row) {:?}", cell, core);
+        }
+    }
+
+    let height = trace.height();
+    for scope in [Scope::Trace { height }, Scope::Window] {
+        let redundant =
+            find_redundant_constraints::<Val, _>(&RoundFlagsAir {}, None, scope, &config)
+                .expect("no preprocessed trace")
+                .iter()
+                .enumerate()
+                .filter(|(_, r)| **r == Redundancy::Redundant)
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>();
+        println!("Redundant constraints over {:?}: {:?}", scope, redundant);
+    }
+
+    for (name, property) in [
+        ("uniqueness", Property::Uniqueness(&trace)),
+        ("determinism", Property::Determinism { height }),
+    ] {
+        let subset = minimal_constraint_subset(&RoundFlagsAir {}, None, property, &config)
+            .expect("trace as wide as the AIR");
+        println!("Minimal constraints for {}: {:?}", name, subset);
+    }
+
+    let determinism =
+        check_deterministic::<Val, _>(&RoundFlagsAir {}, trace.height(), None, &config)
+            .expect("no preprocessed trace");
+    match determinism {
+        DeterminismResult::Deterministic => println!("Deterministic"),
+        DeterminismResult::Nondeterministic { diff, .. } => {
+            println!("Nondeterministic at {:?}", diff)
+        }
+        DeterminismResult::Unknown(reason) => println!("Unknown: {}", reason),
+    }
+
+    let statuses = check_windows(
+        &RoundFlagsAir {},
+        &trace,
+        None,
+        4,
+        Boundary::Pinned,
+        &config,
+    )
+    .expect("no preprocessed trace");
+    println!("Rows by window: {:?}", statuses);
+
+    let induction = prove_by_induction::<Val, _>(&RoundFlagsAir {}, 4, &config);
+    println!("Induction: {:?}", induction);
 }
@@ -0,0 +1,180 @@
+use p3_air::Air;
+use p3_field::PrimeField64;
+use p3_matrix::dense::RowMajorMatrix;
+use p3_matrix::Matrix;
+use p3_uni_stark::SymbolicExpression;
+use z3::ast::Bool;
+use z3::{Context, SatResult};
+
+use crate::check_unconstrained::{
+    check_preprocessed_height, dispatch_encoding, CheckConfig, CheckError,
+};
+use crate::context::context;
+use crate::field::FieldElement;
+use crate::symbolic::{
+    assert_guarded_constraints, cells_agree, constraint_literals, pin_and_differ,
+    symbolic_constraints, trace_consts, SharedTerms, TraceVars,
+};
+use crate::symbolic_builder::ExtendedSymbolicAirBuilder;
+
+/// The soundness property a subset of the constraints must preserve.
+#[derive(Clone, Copy, Debug)]
+pub enum Property<'a, F> {
+    /// No trace other than the given honest one satisfies the constraints, as in
+    /// `check_unconstrained`.
+    Uniqueness(&'a RowMajorMatrix<F>),
+    /// Any two traces of this height that agree on the inputs agree everywhere, as in
+    /// `check_deterministic`.
+    Determinism { height: usize },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MinimalSubset {
+    /// The indices of a subset of the constraints that preserves the property, none of which can be
+    /// dropped on its own.
+    Found(Vec<usize>),
+    /// The property does not hold even with every constraint.
+    PropertyFails,
+    /// The solver gave up on the full set of constraints; holds `Solver::get_reason_unknown`.
+    Unknown(String),
+}
+
+/// Searches for a minimal subset of the constraints of `air` that still preserves `property`, i.e.
+/// a minimal unsatisfiable subset of the constraints for the query asking the property to fail.
+/// Each constraint is a group of assumptions, one per row. Constraints are dropped one at a time,
+/// and each successful query shrinks the remaining set to its unsat core. A constraint the solver
+/// gives up on is kept, so the subset then still preserves the property but may not be minimal.
+///
+/// The constraints outside the subset are the ones the soundness argument does not rest on. As in
+/// the other checks, the solver picks the challenges of a multi-stage AIR.
+pub fn minimal_constraint_subset<F, A>(
+    air: &A,
+    preprocessed: Option<&RowMajorMatrix<F>>,
+    property: Property<'_, F>,
+    config: &CheckConfig,
+) -> Result<MinimalSubset, CheckError>
+where
+    F: PrimeField64,
+    A: Air<ExtendedSymbolicAirBuilder<F>>,
+{
+    let width = air.width();
+    let height = match property {
+        Property::Uniqueness(main) if main.width() != width => {
+            return Err(CheckError::MainWidth {
+                air: width,
+                main: main.width(),
+            })
+        }
+        Property::Uniqueness(main) => main.height(),
+        Property::Determinism { height } => height,
+    };
+    check_preprocessed_height(height, preprocessed)?;
+    let preprocessed_width = preprocessed.map_or(0, |p| p.width());
+    let constraints = symbolic_constraints(air, config.layout(width, preprocessed_width));
+    let subset = dispatch_encoding!(
+        config.encoding,
+        F,
+        search(
+            context(),
+            &constraints,
+            width,
+            preprocessed,
+            property,
+            config
+        )
+    );
+    Ok(subset)
+}
+
+enum Outcome {
+    /// The property holds with the constraints in the unsat core.
+    Holds(Vec<usize>),
+    Fails,
+    Unknown(String),
+}
+
+fn search<'ctx, E>(
+    ctx: &'ctx Context,
+    constraints: &[SymbolicExpression<E::F>],
+    width: usize,
+    preprocessed: Option<&RowMajorMatrix<E::F>>,
+    property: Property<'_, E::F>,
+    config: &CheckConfig,
+) -> MinimalSubset
+where
+    E: FieldElement<'ctx>,
+{
+    let solver = config.solver(ctx);
+
+    let shared = SharedTerms::<E>::new(&solver, preprocessed, config, false);
+    let guard = |vars: &TraceVars<E>, literals: &[((usize, usize), Bool<'ctx>)]| {
+        assert_guarded_constraints(&solver, constraints, &shared.terms(vars), literals);
+    };
+
+    // Whether each cell agrees between the two traces the property compares
+    let (literals, agrees) = match property {
+        Property::Uniqueness(main) => {
+            let height = main.height();
+            let vars = TraceVars::<E>::new(&solver, ("T", "S"), width, height, config, false);
+            let literals = constraint_literals(ctx, constraints.len(), height);
+            guard(&vars, &literals);
+            (literals, cells_agree(&vars.main, &trace_consts(ctx, main)))
+        }
+        Property::Determinism { height } => {
+            let left = TraceVars::<E>::new(&solver, ("L", "SL"), width, height, config, false);
+            let right = TraceVars::<E>::new(&solver, ("R", "SR"), width, height, config, false);
+            // Both copies share their literals, so an assumption drops a constraint from both
+            let literals = constraint_literals(ctx, constraints.len(), height);
+            guard(&left, &literals);
+            guard(&right, &literals);
+            (literals, cells_agree(&left.main, &right.main))
+        }
+    };
+
+    // Pin the inputs, and ask for some other cell to differ
+    pin_and_differ(&solver, config.split_inputs(width, agrees));
+
+    let check = |kept: &[usize]| {
+        let assumptions = literals
+            .iter()
+            .filter(|((constraint, _), _)| kept.contains(constraint))
+            .map(|(_, literal)| literal.clone())
+            .collect::<Vec<_>>();
+        match solver.check_assumptions(&assumptions) {
+            SatResult::Unsat => {
+                let core = solver.get_unsat_core();
+                let needed = kept
+                    .iter()
+                    .copied()
+                    .filter(|&constraint| {
+                        literals
+                            .iter()
+                            .any(|((c, _), literal)| *c == constraint && core.contains(literal))
+                    })
+                    .collect();
+                Outcome::Holds(needed)
+            }
+            SatResult::Sat => Outcome::Fails,
+            SatResult::Unknown => Outcome::Unknown(solver.get_reason_unknown().unwrap_or_default()),
+        }
+    };
+
+    let mut kept = match check(&(0..constraints.len()).collect::<Vec<_>>()) {
+        Outcome::Holds(core) => core,
+        Outcome::Fails => return MinimalSubset::PropertyFails,
+        Outcome::Unknown(reason) => return MinimalSubset::Unknown(reason),
+    };
+    // Every constraint before `i` is needed, or was kept when the solver gave up on it
+    let mut i = 0;
+    while i < kept.len() {
+        let candidate = [&kept[..i], &kept[i + 1..]].concat();
+        match check(&candidate) {
+            Outcome::Holds(core) => {
+                i = core.iter().filter(|c| kept[..i].contains(c)).count();
+                kept = core;
+            }
+            Outcome::Fails | Outcome::Unknown(_) => i += 1,
+        }
+    }
+    MinimalSubset::Found(kept)
+}
@@ -0,0 +1,244 @@
+use core::fmt;
+use p3_field::PrimeField64;
+use std::{
+    marker::PhantomData,
+    ops::{Add, Mul, Neg, Sub},
+};
+use z3::{ast::*, *};
+use z3_sys::*;
+
+use crate::field::FieldElement;
+
+/// A prime field element as an unreduced Z3 `Int`. Operations build plain integer terms, and the
+/// result is only reduced when it is asserted to be zero, by a fresh quotient `k` with
+/// `raw = k * p`. This avoids the nested `mod` terms `Felt` produces for deep expressions.
+///
+/// The range of `raw` is tracked while it fits in an `i128`, which bounds `k`. `_eq` compares the
+/// raw integers, so it only means equality in the field for canonical terms such as variables.
+#[derive(Clone, Hash, PartialEq, Eq)]
+pub struct QuotientFelt<'ctx, F: PrimeField64> {
+    raw: Int<'ctx>,
+    /// Inclusive bounds on `raw`, if they are known.
+    bounds: Option<(i128, i128)>,
+    _marker: PhantomData<F>,
+}
+
+impl<'ctx, F: PrimeField64> QuotientFelt<'ctx, F> {
+    fn new(raw: Int<'ctx>, bounds: Option<(i128, i128)>) -> Self {
+        Self {
+            raw,
+            bounds,
+            _marker: PhantomData,
+        }
+    }
+
+    fn canonical_bounds() -> Option<(i128, i128)> {
+        Some((0, F::ORDER_U64 as i128 - 1))
+    }
+
+    fn modulus(ctx: &'ctx Context) -> Int<'ctx> {
+        Int::from_u64(ctx, F::ORDER_U64)
+    }
+
+    fn from_i128(ctx: &'ctx Context, x: i128) -> Int<'ctx> {
+        Int::from_str(ctx, &x.to_string()).unwrap()
+    }
+}
+
+impl<'ctx, F: PrimeField64> FieldElement<'ctx> for QuotientFelt<'ctx, F> {
+    type F = F;
+
+    fn new_const(solver: &Solver<'ctx>, name: String) -> Self {
+        let x = Self::new_unbounded(solver.get_context(), name);
+        solver.assert(&x.is_canonical());
+        x
+    }
+
+    fn new_unbounded(ctx: &'ctx Context, name: String) -> Self {
+        // The quantifier binding it restricts it to canonical values
+        Self::new(Int::new_const(ctx, name), Self::canonical_bounds())
+    }
+
+    fn from_u64(ctx: &'ctx Context, u: u64) -> Self {
+        assert!(u < F::ORDER_U64);
+        Self::new(Int::from_u64(ctx, u), Some((u as i128, u as i128)))
+    }
+
+    fn as_u64(&self) -> Option<u64> {
+        self.raw.as_u64()
+    }
+
+    fn is_canonical(&self) -> Bool<'ctx> {
+        let ctx = self.get_ctx();
+        let zero = Int::from_u64(ctx, 0);
+        Bool::and(ctx, &[self.raw.ge(&zero), self.raw.lt(&Self::modulus(ctx))])
+    }
+
+    fn is_zero(&self) -> Bool<'ctx> {
+        // A formula may sit under a quantifier, where no quotient can be introduced
+        let ctx = self.get_ctx();
+        self.raw
+            .modulo(&Self::modulus(ctx))
+            ._eq(&Int::from_u64(ctx, 0))
+    }
+
+    fn assert_zero(&self, solver: &Solver<'ctx>) {
+        let ctx = solver.get_context();
+        let p = F::ORDER_U64 as i128;
+        let k = Int::fresh_const(ctx, "k");
+        if let Some((lo, hi)) = self.bounds {
+            // `k` lies between `ceil(lo / p)` and `floor(hi / p)`
+            let lo = -(-lo).div_euclid(p);
+            let hi = hi.div_euclid(p);
+            solver.assert(&k.ge(&Self::from_i128(ctx, lo)));
+            solver.assert(&k.le(&Self::from_i128(ctx, hi)));
+        }
+        solver.assert(&self.raw._eq(&Int::mul(ctx, &[&k, &Self::modulus(ctx)])));
+    }
+}
+
+impl<'ctx, F: PrimeField64> Ast<'ctx> for QuotientFelt<'ctx, F> {
+    unsafe fn wrap(ctx: &'ctx Context, ast: Z3_ast) -> Self {
+        Self::new(Int::wrap(ctx, ast), None)
+    }
+
+    fn get_ctx(&self) -> &'ctx Context {
+        self.raw.get_ctx()
+    }
+
+    fn get_z3_ast(&self) -> Z3_ast {
+        self.raw.get_z3_ast()
+    }
+}
+
+impl<'ctx, F: PrimeField64> fmt::Debug for QuotientFelt<'ctx, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        self.raw.fmt(f)
+    }
+}
+
+impl<'ctx, F: PrimeField64> fmt::Display for QuotientFelt<'ctx, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        <Self as fmt::Debug>::fmt(self, f)
+    }
+}
+
+impl<'ctx, F: PrimeField64> Add<&QuotientFelt<'ctx, F>> for &QuotientFelt<'ctx, F> {
+    type Output = QuotientFelt<'ctx, F>;
+
+    fn add(self, other: &QuotientFelt<'ctx, F>) -> Self::Output {
+        let bounds = self
+            .bounds
+            .zip(other.bounds)
+            .and_then(|((a, b), (c, d))| Some((a.checked_add(c)?, b.checked_add(d)?)));
+        QuotientFelt::new(Int::add(self.get_ctx(), &[&self.raw, &other.raw]), bounds)
+    }
+}
+
+impl<'ctx, F: PrimeField64> Add<QuotientFelt<'ctx, F>> for QuotientFelt<'ctx, F> {
+    type Output = QuotientFelt<'ctx, F>;
+
+    fn add(self, other: QuotientFelt<'ctx, F>) -> Self::Output {
+        &self + &other
+    }
+}
+
+impl<'ctx, F: PrimeField64> Sub<&QuotientFelt<'ctx, F>> for &QuotientFelt<'ctx, F> {
+    type Output = QuotientFelt<'ctx, F>;
+
+    fn sub(self, other: &QuotientFelt<'ctx, F>) -> Self::Output {
+        let bounds = self
+            .bounds
+            .zip(other.bounds)
+            .and_then(|((a, b), (c, d))| Some((a.checked_sub(d)?, b.checked_sub(c)?)));
+        QuotientFelt::new(Int::sub(self.get_ctx(), &[&self.raw, &other.raw]), bounds)
+    }
+}
+
+impl<'ctx, F: PrimeField64> Sub<QuotientFelt<'ctx, F>> for QuotientFelt<'ctx, F> {
+    type Output = QuotientFelt<'ctx, F>;
+
+    fn sub(self, other: QuotientFelt<'ctx, F>) -> Self::Output {
+        &self - &other
+    }
+}
+
+impl<'ctx, F: PrimeField64> Mul<&QuotientFelt<'ctx, F>> for &QuotientFelt<'ctx, F> {
+    type Output = QuotientFelt<'ctx, F>;
+
+    fn mul(self, other: &QuotientFelt<'ctx, F>) -> Self::Output {
+        let bounds = self.bounds.zip(other.bounds).and_then(|((a, b), (c, d))| {
+            let corners = [
+                a.checked_mul(c)?,
+                a.checked_mul(d)?,
+                b.checked_mul(c)?,
+                b.checked_mul(d)?,
+            ];
+            Some((*corners.iter().min()?, *corners.iter().max()?))
+        });
+        QuotientFelt::new(Int::mul(self.get_ctx(), &[&self.raw, &other.raw]), bounds)
+    }
+}
+
+impl<'ctx, F: PrimeField64> Mul<QuotientFelt<'ctx, F>> for QuotientFelt<'ctx, F> {
+    type Output = QuotientFelt<'ctx, F>;
+
+    fn mul(self, other: QuotientFelt<'ctx, F>) -> Self::Output {
+        &self * &other
+    }
+}
+
+impl<'ctx, F: PrimeField64> Neg for QuotientFelt<'ctx, F> {
+    type Output = QuotientFelt<'ctx, F>;
+
+    fn neg(self) -> Self::Output {
+        let bounds = self
+            .bounds
+            .and_then(|(lo, hi)| Some((hi.checked_neg()?, lo.checked_neg()?)));
+        QuotientFelt::new(self.raw.unary_minus(), bounds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use p3_baby_bear::BabyBear;
+    use p3_field::AbstractField;
+    use rand::random;
+
+    use super::*;
+    use crate::context::context;
+    use crate::field::tests::cross_check_arithmetic;
+
+    type Q = QuotientFelt<'static, BabyBear>;
+
+    #[test]
+    fn quotient_felt_matches_native_arithmetic() {
+        cross_check_arithmetic::<Q>(context());
+    }
+
+    #[test]
+    fn bounds_contain_the_unreduced_value() {
+        let ctx = context();
+        let p = BabyBear::ORDER_U64;
+        for _ in 0..20 {
+            let [a, b, c] = [(); 3].map(|_| random::<u64>() % p);
+            let lift = |x| Q::from_u64(ctx, x);
+            let term = -(lift(a) * lift(b) - lift(c)) + lift(a);
+            let raw = -((a as i128) * (b as i128) - c as i128) + a as i128;
+            let (lo, hi) = term.bounds.expect("fits in an i128");
+            assert!(lo <= raw && raw <= hi, "{} outside [{}, {}]", raw, lo, hi);
+            assert_eq!(term.raw.simplify().as_i64().map(i128::from), Some(raw));
+        }
+    }
+
+    #[test]
+    fn bounds_are_dropped_on_overflow() {
+        let ctx = context();
+        let mut term = Q::from_f(ctx, BabyBear::neg_one());
+        for _ in 0..3 {
+            term = term.clone() * term;
+        }
+        // `(p - 1)^8` no longer fits in an `i128`
+        assert_eq!(term.bounds, None);
+    }
+}
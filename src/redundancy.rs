@@ -0,0 +1,180 @@
+use p3_air::Air;
+use p3_field::PrimeField64;
+use p3_matrix::dense::RowMajorMatrix;
+use p3_matrix::Matrix;
+use p3_uni_stark::SymbolicExpression;
+use z3::ast::Bool;
+use z3::{Context, SatResult, Solver};
+
+use crate::check_unconstrained::{
+    check_preprocessed_height, dispatch_encoding, CheckConfig, CheckError, PublicValues,
+};
+use crate::context::context;
+use crate::field::FieldElement;
+use crate::symbolic::{
+    new_trace_vars, parse_row_constraints, symbolic_constraints, Row, Selectors, SharedTerms,
+    TraceVars,
+};
+use crate::symbolic_builder::ExtendedSymbolicAirBuilder;
+
+/// Which traces a constraint must be implied on to be reported redundant.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scope {
+    /// Every trace of `height` rows, with the preprocessed columns, if any, fixed.
+    Trace { height: usize },
+    /// Every pair of `local` and `next` rows, including the preprocessed cells, for each way the
+    /// row selectors can be set. A constraint implied here is implied on traces of any height, but
+    /// one that is only implied through rows further away is reported as needed.
+    Window,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Redundancy {
+    /// The other constraints imply this one, so it can be dropped.
+    Redundant,
+    /// Some assignment satisfies the other constraints but not this one.
+    Needed,
+    /// The solver gave up on this constraint.
+    Unknown,
+}
+
+/// Asks, for every constraint of `air`, whether the other constraints imply it within `scope`. The
+/// result has one entry per constraint. Universal public values are treated as free, so a
+/// constraint is redundant when the others imply it for each choice of public values.
+pub fn find_redundant_constraints<F, A>(
+    air: &A,
+    preprocessed: Option<&RowMajorMatrix<F>>,
+    scope: Scope,
+    config: &CheckConfig,
+) -> Result<Vec<Redundancy>, CheckError>
+where
+    F: PrimeField64,
+    A: Air<ExtendedSymbolicAirBuilder<F>>,
+{
+    if let Scope::Trace { height } = scope {
+        check_preprocessed_height(height, preprocessed)?;
+    }
+    let width = air.width();
+    let preprocessed_width = preprocessed.map_or(0, |p| p.width());
+    let constraints = symbolic_constraints(air, config.layout(width, preprocessed_width));
+    let redundancies = dispatch_encoding!(
+        config.encoding,
+        F,
+        check_scope(context(), &constraints, width, preprocessed, scope, config)
+    );
+    Ok(redundancies)
+}
+
+fn check_scope<'ctx, E>(
+    ctx: &'ctx Context,
+    constraints: &[SymbolicExpression<E::F>],
+    width: usize,
+    preprocessed: Option<&RowMajorMatrix<E::F>>,
+    scope: Scope,
+    config: &CheckConfig,
+) -> Vec<Redundancy>
+where
+    E: FieldElement<'ctx>,
+{
+    let solver = config.solver(ctx);
+    let config = &CheckConfig {
+        public_values: match &config.public_values {
+            PublicValues::Universal(n) => PublicValues::Free(*n),
+            public_values => public_values.clone(),
+        },
+        ..config.clone()
+    };
+
+    match scope {
+        Scope::Trace { height } => {
+            let shared = SharedTerms::<E>::new(&solver, preprocessed, config, false);
+            let vars = TraceVars::<E>::new(&solver, ("T", "S"), width, height, config, false);
+            let terms = shared.terms(&vars);
+            let rows = (0..height).map(|i| terms.row(i)).collect::<Vec<_>>();
+            check_rows(&solver, constraints, &rows)
+        }
+        Scope::Window => {
+            // The selectors of a first, middle and last row, and of the only row of a trace
+            let cases = [(0, 2), (1, 3), (1, 2), (0, 1)];
+            let mut redundancies = vec![Redundancy::Redundant; constraints.len()];
+            for (row, height) in cases {
+                // The cases are independent pairs of rows, so each gets its own scope
+                solver.push();
+                // The only row of a trace is its own next row
+                let rows = height.min(2);
+                let preprocessed =
+                    preprocessed.map(|p| new_trace_vars::<E>(&solver, "Q", p.width(), rows));
+                let shared =
+                    SharedTerms::<E>::with_preprocessed(&solver, preprocessed, config, false);
+                let vars = TraceVars::<E>::new(&solver, ("W", "S"), width, rows, config, false);
+                let row = Row {
+                    selectors: Selectors::for_row(row, height),
+                    ..shared.terms(&vars).row(0)
+                };
+                let case = check_rows(&solver, constraints, &[row]);
+                solver.pop(1);
+
+                for (redundancy, case) in redundancies.iter_mut().zip(case) {
+                    *redundancy = match (*redundancy, case) {
+                        (Redundancy::Needed, _) | (_, Redundancy::Needed) => Redundancy::Needed,
+                        (Redundancy::Unknown, _) | (_, Redundancy::Unknown) => Redundancy::Unknown,
+                        _ => Redundancy::Redundant,
+                    };
+                }
+            }
+            redundancies
+        }
+    }
+}
+
+/// Asserts every constraint on `rows`, guarded by one literal per constraint, then asks for each
+/// constraint whether it can fail on some row while the literals of the others hold.
+fn check_rows<'ctx, E>(
+    solver: &Solver<'ctx>,
+    constraints: &[SymbolicExpression<E::F>],
+    rows: &[Row<'_, E>],
+) -> Vec<Redundancy>
+where
+    E: FieldElement<'ctx>,
+{
+    let ctx = solver.get_context();
+    let holds = rows
+        .iter()
+        .map(|&row| {
+            parse_row_constraints(constraints, ctx, row)
+                .iter()
+                .map(E::is_zero)
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let literals = (0..constraints.len())
+        .map(|j| Bool::new_const(ctx, format!("constraint[{}]", j)))
+        .collect::<Vec<_>>();
+    for row in &holds {
+        for (literal, constraint_holds) in literals.iter().zip(row) {
+            solver.assert(&literal.implies(constraint_holds));
+        }
+    }
+
+    (0..constraints.len())
+        .map(|j| {
+            let fails = holds.iter().map(|row| row[j].not()).collect::<Vec<_>>();
+            let others = literals
+                .iter()
+                .enumerate()
+                .filter(|&(k, _)| k != j)
+                .map(|(_, literal)| literal.clone())
+                .collect::<Vec<_>>();
+
+            solver.push();
+            solver.assert(&Bool::or(ctx, &fails));
+            let redundancy = match solver.check_assumptions(&others) {
+                SatResult::Unsat => Redundancy::Redundant,
+                SatResult::Sat => Redundancy::Needed,
+                SatResult::Unknown => Redundancy::Unknown,
+            };
+            solver.pop(1);
+            redundancy
+        })
+        .collect()
+}
@@ -0,0 +1,122 @@
+use std::collections::{BTreeSet, HashMap};
+
+use p3_air::Air;
+use p3_field::PrimeField64;
+use p3_uni_stark::SymbolicExpression;
+
+use crate::symbolic::symbolic_constraints;
+use crate::symbolic_builder::{ExtendedSymbolicAirBuilder, TraceLayout};
+
+/// Which rows of the trace a constraint reads.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RowUsage {
+    /// Only selectors, constants, public values or challenges.
+    Neither,
+    Local,
+    Next,
+    Both,
+}
+
+/// Static statistics about one constraint, from its `SymbolicExpression` alone.
+#[derive(Clone, Debug)]
+pub struct ConstraintStats {
+    /// The degree in the trace columns. Unlike `SymbolicExpression::degree_multiple`, public values
+    /// and challenges count as constants, since they are fixed for the whole trace.
+    pub degree: usize,
+    /// The number of distinct nodes, counting a subexpression shared through `Rc` once, as it is
+    /// translated to Z3.
+    pub nodes: usize,
+    /// The columns referenced, laid out as in `TraceLayout`.
+    pub columns: BTreeSet<usize>,
+    pub rows: RowUsage,
+    /// Whether `degree` is above the maximum the report was asked for.
+    pub exceeds_max_degree: bool,
+}
+
+/// Reports the degree, size and columns of every constraint of `air`, flagging those whose degree
+/// exceeds `max_degree`, e.g. the degree the quotient is configured for. High-degree and large
+/// constraints are the ones likely to make the solver struggle.
+pub fn constraint_report<F, A>(
+    air: &A,
+    layout: TraceLayout,
+    max_degree: usize,
+) -> Vec<ConstraintStats>
+where
+    F: PrimeField64,
+    A: Air<ExtendedSymbolicAirBuilder<F>>,
+{
+    let trace_width = layout.width + layout.preprocessed_width + layout.permutation_width;
+    symbolic_constraints(air, layout)
+        .iter()
+        .map(|constraint| {
+            let mut visitor = Visitor::new(trace_width);
+            let degree = visitor.visit(constraint);
+            let uses_local = visitor.local.iter().any(|&c| c < trace_width);
+            let uses_next = visitor.next.iter().any(|&c| c < trace_width);
+            ConstraintStats {
+                degree,
+                nodes: visitor.degrees.len(),
+                columns: visitor.local.union(&visitor.next).copied().collect(),
+                rows: match (uses_local, uses_next) {
+                    (false, false) => RowUsage::Neither,
+                    (true, false) => RowUsage::Local,
+                    (false, true) => RowUsage::Next,
+                    (true, true) => RowUsage::Both,
+                },
+                exceeds_max_degree: degree > max_degree,
+            }
+        })
+        .collect()
+}
+
+/// Walks each distinct node of an expression once.
+struct Visitor<F> {
+    /// The columns below this are trace columns; those above are public values and challenges.
+    trace_width: usize,
+    /// The degree of each node visited so far.
+    degrees: HashMap<*const SymbolicExpression<F>, usize>,
+    /// The columns read on the local row.
+    local: BTreeSet<usize>,
+    /// The columns read on the next row.
+    next: BTreeSet<usize>,
+}
+
+impl<F> Visitor<F> {
+    fn new(trace_width: usize) -> Self {
+        Self {
+            trace_width,
+            degrees: HashMap::new(),
+            local: BTreeSet::new(),
+            next: BTreeSet::new(),
+        }
+    }
+
+    /// Records the columns `exp` reads and returns its degree, with the selectors counted as in
+    /// `degree_multiple`.
+    fn visit(&mut self, exp: &SymbolicExpression<F>) -> usize {
+        let key = exp as *const _;
+        if let Some(&degree) = self.degrees.get(&key) {
+            return degree;
+        }
+        let degree = match exp {
+            SymbolicExpression::Variable(var) => {
+                let columns = if var.is_next {
+                    &mut self.next
+                } else {
+                    &mut self.local
+                };
+                columns.insert(var.column);
+                (var.column < self.trace_width) as usize
+            }
+            SymbolicExpression::IsFirstRow | SymbolicExpression::IsLastRow => 1,
+            SymbolicExpression::IsTransition | SymbolicExpression::Constant(_) => 0,
+            SymbolicExpression::Add { x, y, .. } | SymbolicExpression::Sub { x, y, .. } => {
+                self.visit(x).max(self.visit(y))
+            }
+            SymbolicExpression::Mul { x, y, .. } => self.visit(x) + self.visit(y),
+            SymbolicExpression::Neg { x, .. } => self.visit(x),
+        };
+        self.degrees.insert(key, degree);
+        degree
+    }
+}
@@ -63,3 +63,179 @@ impl<AB: AirBuilder> Air<AB> for RoundFlagsAir {
         //     .assert_zero(next_any_flag * (current_any_flag - AB::Expr::one()));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use p3_baby_bear::BabyBear;
+    use p3_matrix::Matrix;
+
+    use super::*;
+    use crate::check_unconstrained::{
+        check_unconstrained_validated, CheckConfig, CheckResult, Counterexample, Encoding,
+    };
+    use crate::determinism::{determinism_map, CellStatus};
+    use crate::induction::{prove_by_induction, InductionResult};
+    use crate::round_flags_air::generate_trace_rows;
+    use crate::symbolic_builder::ExtendedSymbolicAirBuilder;
+    use crate::window::{check_windows, Boundary, RowStatus};
+
+    type Val = BabyBear;
+
+    /// `RoundFlagsAir` without the first-row constraint on `step_flags[1]`, so a nonzero flag can
+    /// enter on the first row and rotate through the whole trace.
+    struct WeakenedAir {}
+
+    impl<F> BaseAir<F> for WeakenedAir {
+        fn width(&self) -> usize {
+            NUM_ROUND_FLAGS_COLS
+        }
+    }
+
+    impl<AB: AirBuilder> Air<AB> for WeakenedAir {
+        fn eval(&self, builder: &mut AB) {
+            let main = builder.main();
+            let local: &RoundFlagsCols<AB::Var> = main.row_slice(0).borrow();
+            let next: &RoundFlagsCols<AB::Var> = main.row_slice(1).borrow();
+
+            builder.when_first_row().assert_one(local.step_flags[0]);
+            for i in 2..NUM_ROUNDS {
+                builder.when_first_row().assert_zero(local.step_flags[i]);
+            }
+
+            for i in 0..NUM_ROUNDS {
+                let current_round_flag = local.step_flags[i];
+                let next_round_flag = next.step_flags[(i + 1) % NUM_ROUNDS];
+                builder
+                    .when_transition()
+                    .assert_eq(next_round_flag, current_round_flag);
+            }
+        }
+    }
+
+    /// The cells the unconstrained flag of `WeakenedAir` moves through, in the first `rows` rows.
+    fn rotating_cells(rows: usize) -> Vec<(usize, usize)> {
+        (0..rows).map(|row| (row, (row + 1) % NUM_ROUNDS)).collect()
+    }
+
+    #[test]
+    fn honest_trace_is_constrained() {
+        let trace = generate_trace_rows::<Val>();
+        for encoding in Encoding::ALL {
+            let config = CheckConfig {
+                encoding,
+                ..CheckConfig::default()
+            };
+            let result = check_unconstrained_validated(&RoundFlagsAir {}, &trace, &config).unwrap();
+            assert!(
+                matches!(result, CheckResult::Constrained),
+                "{:?}: {:?}",
+                encoding,
+                result
+            );
+        }
+    }
+
+    #[test]
+    fn weakened_air_is_underconstrained() {
+        let trace = generate_trace_rows::<Val>();
+        for encoding in Encoding::ALL {
+            let config = CheckConfig {
+                encoding,
+                ..CheckConfig::default()
+            };
+            match check_unconstrained_validated(&WeakenedAir {}, &trace, &config).unwrap() {
+                CheckResult::Underconstrained(counterexamples) => {
+                    assert_eq!(counterexamples.len(), 1);
+                    assert_eq!(counterexamples[0].diff, rotating_cells(trace.height()));
+                }
+                result => panic!("{:?}: {:?}", encoding, result),
+            }
+        }
+    }
+
+    #[test]
+    fn determinism_map_marks_the_rotating_cells() {
+        let trace = generate_trace_rows::<Val>();
+        let config = CheckConfig::default();
+
+        let map = determinism_map(&RoundFlagsAir {}, &trace, None, &config).unwrap();
+        assert!(map.values.iter().all(|s| *s == CellStatus::Determined));
+
+        let map = determinism_map(&WeakenedAir {}, &trace, None, &config).unwrap();
+        let free = rotating_cells(trace.height());
+        for (i, status) in map.values.iter().enumerate() {
+            let cell = (i / map.width(), i % map.width());
+            let expected = if free.contains(&cell) {
+                CellStatus::Free
+            } else {
+                CellStatus::Determined
+            };
+            assert_eq!(*status, expected, "{:?}", cell);
+        }
+    }
+
+    /// Checks the windowed statuses of `air` against its determinism map. A window over the whole
+    /// trace is the same query row by row; smaller windows may only miss freedom when pinned and
+    /// only miss determinism when symbolic.
+    fn assert_windows_agree<A>(air: &A)
+    where
+        A: Air<ExtendedSymbolicAirBuilder<Val>>,
+    {
+        let trace = generate_trace_rows::<Val>();
+        let height = trace.height();
+        let config = CheckConfig::default();
+        let map = determinism_map(air, &trace, None, &config).unwrap();
+        let free_rows = map
+            .values
+            .chunks(map.width())
+            .map(|row| row.contains(&CellStatus::Free))
+            .collect::<Vec<_>>();
+        let windows =
+            |size, boundary| check_windows(air, &trace, None, size, boundary, &config).unwrap();
+
+        let whole = windows(height, Boundary::Pinned);
+        let pinned = windows(4, Boundary::Pinned);
+        let symbolic = windows(4, Boundary::Symbolic);
+        for row in 0..height {
+            let free = free_rows[row];
+            let expected = if free {
+                RowStatus::Free
+            } else {
+                RowStatus::Determined
+            };
+            assert_eq!(whole[row], expected, "row {}", row);
+            assert!(free || pinned[row] != RowStatus::Free, "row {}", row);
+            assert!(
+                !free || symbolic[row] != RowStatus::Determined,
+                "row {}",
+                row
+            );
+        }
+    }
+
+    #[test]
+    fn windows_agree_with_determinism_map() {
+        assert_windows_agree(&RoundFlagsAir {});
+        assert_windows_agree(&WeakenedAir {});
+    }
+
+    #[test]
+    fn induction_agrees_with_determinism_map() {
+        let config = CheckConfig::default();
+        let result = prove_by_induction::<Val, _>(&RoundFlagsAir {}, 4, &config);
+        assert!(
+            matches!(result, InductionResult::Proved { k: 1 }),
+            "{:?}",
+            result
+        );
+
+        match prove_by_induction::<Val, _>(&WeakenedAir {}, 4, &config) {
+            InductionResult::BaseCaseFailed { k, left, right } => {
+                assert_eq!(k, 4);
+                let diff = Counterexample::new(right, &left).diff;
+                assert_eq!(diff, rotating_cells(k + 1));
+            }
+            result => panic!("{:?}", result),
+        }
+    }
+}
@@ -0,0 +1,596 @@
+use std::collections::HashMap;
+
+use p3_air::Air;
+use p3_field::PrimeField64;
+use p3_matrix::dense::RowMajorMatrix;
+use p3_matrix::{Matrix, MatrixRowSlices};
+use p3_uni_stark::{SymbolicExpression, SymbolicVariable};
+use z3::ast::{exists_const, forall_const, Ast, Bool};
+use z3::{Context, Model, Solver};
+
+use crate::check_unconstrained::{CheckConfig, PublicValues};
+use crate::field::FieldElement;
+use crate::symbolic_builder::{ExtendedSymbolicAirBuilder, TraceLayout};
+
+/// Collects the constraints `air` places on a trace with the given layout.
+pub(crate) fn symbolic_constraints<F, A>(air: &A, layout: TraceLayout) -> Vec<SymbolicExpression<F>>
+where
+    F: PrimeField64,
+    A: Air<ExtendedSymbolicAirBuilder<F>>,
+{
+    let mut builder = ExtendedSymbolicAirBuilder::new(layout);
+    air.eval(&mut builder);
+    builder.constraints()
+}
+
+/// Creates a fresh variable `{name}[r][c]` for every cell of a `height x width` trace.
+pub(crate) fn new_trace_vars<'ctx, E>(
+    solver: &Solver<'ctx>,
+    name: &str,
+    width: usize,
+    height: usize,
+) -> RowMajorMatrix<E>
+where
+    E: FieldElement<'ctx>,
+{
+    RowMajorMatrix::new(
+        (0..width * height)
+            .map(|i| E::new_const(solver, format!("{}[{}][{}]", name, i / width, i % width)))
+            .collect(),
+        width,
+    )
+}
+
+/// A fresh variable, whose range is asserted unless it is to be `quantified`, in which case the
+/// quantifier binding it must assert the range instead.
+fn new_var<'ctx, E>(solver: &Solver<'ctx>, name: String, quantified: bool) -> E
+where
+    E: FieldElement<'ctx>,
+{
+    if quantified {
+        E::new_unbounded(solver.get_context(), name)
+    } else {
+        E::new_const(solver, name)
+    }
+}
+
+/// Creates the second trace stage `{name}[r][c]` of a multi-stage AIR, if it has one.
+pub(crate) fn new_permutation_vars<'ctx, E>(
+    solver: &Solver<'ctx>,
+    name: &str,
+    width: usize,
+    height: usize,
+    quantified: bool,
+) -> Option<RowMajorMatrix<E>>
+where
+    E: FieldElement<'ctx>,
+{
+    (width > 0).then(|| {
+        RowMajorMatrix::new(
+            (0..width * height)
+                .map(|i| {
+                    let name = format!("{}[{}][{}]", name, i / width, i % width);
+                    new_var(solver, name, quantified)
+                })
+                .collect(),
+            width,
+        )
+    })
+}
+
+/// Creates the verifier challenges `C[i]` of a multi-stage AIR.
+pub(crate) fn new_challenge_vars<'ctx, E>(
+    solver: &Solver<'ctx>,
+    num_challenges: usize,
+    quantified: bool,
+) -> Vec<E>
+where
+    E: FieldElement<'ctx>,
+{
+    (0..num_challenges)
+        .map(|i| new_var(solver, format!("C[{}]", i), quantified))
+        .collect()
+}
+
+/// `forall vars. body`, over canonical values of `vars` only.
+pub(crate) fn forall<'ctx, E>(ctx: &'ctx Context, vars: &[E], body: &Bool<'ctx>) -> Bool<'ctx>
+where
+    E: FieldElement<'ctx>,
+{
+    if vars.is_empty() {
+        return body.clone();
+    }
+    let bounds = vars.iter().map(|v| v as &dyn Ast<'ctx>).collect::<Vec<_>>();
+    forall_const(ctx, &bounds, &[], &canonical(ctx, vars).implies(body))
+}
+
+/// `exists vars. body`, over canonical values of `vars` only.
+pub(crate) fn exists<'ctx, E>(ctx: &'ctx Context, vars: &[E], body: &Bool<'ctx>) -> Bool<'ctx>
+where
+    E: FieldElement<'ctx>,
+{
+    if vars.is_empty() {
+        return body.clone();
+    }
+    let bounds = vars.iter().map(|v| v as &dyn Ast<'ctx>).collect::<Vec<_>>();
+    let body = Bool::and(ctx, &[canonical(ctx, vars), body.clone()]);
+    exists_const(ctx, &bounds, &[], &body)
+}
+
+fn canonical<'ctx, E>(ctx: &'ctx Context, vars: &[E]) -> Bool<'ctx>
+where
+    E: FieldElement<'ctx>,
+{
+    Bool::and(ctx, &vars.iter().map(E::is_canonical).collect::<Vec<_>>())
+}
+
+/// Lifts a concrete trace to Z3 constants. Preprocessed columns are always encoded this way, as the
+/// prover cannot change them.
+pub(crate) fn trace_consts<'ctx, E>(
+    ctx: &'ctx Context,
+    main: &RowMajorMatrix<E::F>,
+) -> RowMajorMatrix<E>
+where
+    E: FieldElement<'ctx>,
+{
+    RowMajorMatrix::new(
+        main.values.iter().map(|&f| E::from_f(ctx, f)).collect(),
+        main.width(),
+    )
+}
+
+/// The Z3 terms standing for the public values, encoded as configured by `PublicValues`.
+pub(crate) struct PublicValueVars<E> {
+    values: Vec<E>,
+    /// Whether the constraints must hold for every assignment to `values`.
+    universal: bool,
+}
+
+impl<E> PublicValueVars<E> {
+    /// Public values fixed to the given terms.
+    pub(crate) fn from_values(values: Vec<E>) -> Self {
+        Self {
+            values,
+            universal: false,
+        }
+    }
+}
+
+impl<'ctx, E: FieldElement<'ctx>> PublicValueVars<E> {
+    pub(crate) fn new(solver: &Solver<'ctx>, public_values: &PublicValues) -> Self {
+        let ctx = solver.get_context();
+        let name = |i| format!("P[{}]", i);
+        match public_values {
+            PublicValues::Pinned(values) => Self {
+                values: values.iter().map(|&v| E::from_u64(ctx, v)).collect(),
+                universal: false,
+            },
+            // Bound by the quantifier, so their range is asserted there rather than globally
+            PublicValues::Universal(n) => Self {
+                values: (0..*n).map(|i| new_var(solver, name(i), true)).collect(),
+                universal: true,
+            },
+            PublicValues::Free(n) => Self {
+                values: (0..*n).map(|i| E::new_const(solver, name(i))).collect(),
+                universal: false,
+            },
+        }
+    }
+
+    /// The public values a quantifier must bind, which are all of them if they are universal.
+    pub(crate) fn universal_values(&self) -> &[E] {
+        if self.universal {
+            &self.values
+        } else {
+            &[]
+        }
+    }
+
+    /// Asserts `exp` is zero, for every public value in range if they are universal.
+    fn assert_zero(&self, solver: &Solver<'ctx>, exp: &E) {
+        if self.universal_values().is_empty() {
+            exp.assert_zero(solver);
+            return;
+        }
+
+        solver.assert(&self.is_zero(solver.get_context(), exp));
+    }
+
+    /// Whether `exp` is zero, for every public value in range if they are universal.
+    fn is_zero(&self, ctx: &'ctx Context, exp: &E) -> Bool<'ctx> {
+        forall(ctx, self.universal_values(), &exp.is_zero())
+    }
+}
+
+/// The terms for every part of a trace an AIR can refer to.
+pub(crate) struct TraceTerms<'a, E> {
+    pub main: &'a RowMajorMatrix<E>,
+    pub preprocessed: Option<&'a RowMajorMatrix<E>>,
+    pub permutation: Option<&'a RowMajorMatrix<E>>,
+    pub public_values: &'a PublicValueVars<E>,
+    pub challenges: &'a [E],
+}
+
+impl<'a, E> TraceTerms<'a, E> {
+    /// The terms the constraints see on row `i`, with the last row wrapping around to the first.
+    pub(crate) fn row(&self, i: usize) -> Row<'a, E> {
+        let height = self.main.height();
+        let next = (i + 1) % height;
+        Row {
+            local: self.main.row_slice(i),
+            next: self.main.row_slice(next),
+            preprocessed_local: optional_row(self.preprocessed, i),
+            preprocessed_next: optional_row(self.preprocessed, next),
+            permutation_local: optional_row(self.permutation, i),
+            permutation_next: optional_row(self.permutation, next),
+            public_values: self.public_values,
+            challenges: self.challenges,
+            selectors: Selectors::for_row(i, height),
+        }
+    }
+}
+
+/// A symbolic copy of the trace: the main trace and its second stage, if any.
+pub(crate) struct TraceVars<E> {
+    pub main: RowMajorMatrix<E>,
+    pub permutation: Option<RowMajorMatrix<E>>,
+}
+
+impl<'ctx, E: FieldElement<'ctx>> TraceVars<E> {
+    /// Fresh variables `{main}[r][c]` and `{permutation}[r][c]` for a trace of `height` rows. The
+    /// second stage is `quantified` as in `new_permutation_vars`.
+    pub(crate) fn new(
+        solver: &Solver<'ctx>,
+        (main, permutation): (&str, &str),
+        width: usize,
+        height: usize,
+        config: &CheckConfig,
+        quantified: bool,
+    ) -> Self {
+        Self {
+            main: new_trace_vars(solver, main, width, height),
+            permutation: new_permutation_vars(
+                solver,
+                permutation,
+                config.permutation_width,
+                height,
+                quantified,
+            ),
+        }
+    }
+}
+
+/// The parts of the trace every copy of it in a query shares: the preprocessed columns, the public
+/// values and the challenges.
+pub(crate) struct SharedTerms<E> {
+    pub preprocessed: Option<RowMajorMatrix<E>>,
+    pub public_values: PublicValueVars<E>,
+    pub challenges: Vec<E>,
+}
+
+impl<'ctx, E: FieldElement<'ctx>> SharedTerms<E> {
+    /// The shared terms of a query, with `preprocessed` fixed to its values and the challenges
+    /// `quantified` as in `new_challenge_vars`.
+    pub(crate) fn new(
+        solver: &Solver<'ctx>,
+        preprocessed: Option<&RowMajorMatrix<E::F>>,
+        config: &CheckConfig,
+        quantified: bool,
+    ) -> Self {
+        let preprocessed = preprocessed.map(|p| trace_consts(solver.get_context(), p));
+        Self::with_preprocessed(solver, preprocessed, config, quantified)
+    }
+
+    /// As `new`, with the preprocessed columns already translated, e.g. to symbolic ones.
+    pub(crate) fn with_preprocessed(
+        solver: &Solver<'ctx>,
+        preprocessed: Option<RowMajorMatrix<E>>,
+        config: &CheckConfig,
+        quantified: bool,
+    ) -> Self {
+        Self {
+            preprocessed,
+            public_values: PublicValueVars::new(solver, &config.public_values),
+            challenges: new_challenge_vars(solver, config.num_challenges, quantified),
+        }
+    }
+
+    /// The terms of one copy of the trace.
+    pub(crate) fn terms<'a>(&'a self, vars: &'a TraceVars<E>) -> TraceTerms<'a, E> {
+        TraceTerms {
+            main: &vars.main,
+            preprocessed: self.preprocessed.as_ref(),
+            permutation: vars.permutation.as_ref(),
+            public_values: &self.public_values,
+            challenges: &self.challenges,
+        }
+    }
+}
+
+/// Whether each cell agrees between two traces of the same shape.
+pub(crate) fn cells_agree<'ctx, E>(
+    left: &RowMajorMatrix<E>,
+    right: &RowMajorMatrix<E>,
+) -> Vec<Bool<'ctx>>
+where
+    E: FieldElement<'ctx>,
+{
+    left.values
+        .iter()
+        .zip(right.values.iter())
+        .map(|(l, r)| l._eq(r))
+        .collect()
+}
+
+/// Splits the per-cell `items` of a trace `width` columns wide into those of the cells `pinned`
+/// holds for and those of the rest.
+pub(crate) fn split_cells<T>(
+    width: usize,
+    items: impl IntoIterator<Item = T>,
+    pinned: impl Fn(usize, usize) -> bool,
+) -> (Vec<T>, Vec<T>) {
+    let (pinned, rest): (Vec<_>, Vec<_>) = items
+        .into_iter()
+        .enumerate()
+        .partition(|(i, _)| pinned(i / width, i % width));
+    let strip =
+        |items: Vec<(usize, T)>| -> Vec<T> { items.into_iter().map(|(_, item)| item).collect() };
+    (strip(pinned), strip(rest))
+}
+
+/// Given whether each cell agrees between the two traces a query compares, asserts that the
+/// `pinned` cells agree and that some `free` one does not.
+pub(crate) fn pin_and_differ<'ctx>(
+    solver: &Solver<'ctx>,
+    (pinned, free): (Vec<Bool<'ctx>>, Vec<Bool<'ctx>>),
+) {
+    for agrees in &pinned {
+        solver.assert(agrees);
+    }
+    let differs = free.iter().map(Bool::not).collect::<Vec<_>>();
+    solver.assert(&Bool::or(solver.get_context(), &differs));
+}
+
+/// Asserts every constraint on every row of `terms`.
+pub(crate) fn assert_constraints<'ctx, E>(
+    solver: &Solver<'ctx>,
+    constraints: &[SymbolicExpression<E::F>],
+    terms: &TraceTerms<'_, E>,
+) where
+    E: FieldElement<'ctx>,
+{
+    (0..terms.main.height())
+        .for_each(|i| assert_row_constraints(solver, constraints, terms.row(i)));
+}
+
+/// Asserts every constraint on every row of `terms`, each guarded by its own literal so that an
+/// unsat core can name the constraint instances it needs. Returns the literal of each
+/// `(constraint index, row)`, to be passed to `Solver::check_assumptions`.
+pub(crate) fn assert_tracked_constraints<'ctx, E>(
+    solver: &Solver<'ctx>,
+    constraints: &[SymbolicExpression<E::F>],
+    terms: &TraceTerms<'_, E>,
+) -> Vec<((usize, usize), Bool<'ctx>)>
+where
+    E: FieldElement<'ctx>,
+{
+    let literals =
+        constraint_literals(solver.get_context(), constraints.len(), terms.main.height());
+    assert_guarded_constraints(solver, constraints, terms, &literals);
+    literals
+}
+
+/// A fresh literal for each `(constraint index, row)` of a trace of `height` rows, ordered by row
+/// and then constraint, as `assert_guarded_constraints` takes them.
+pub(crate) fn constraint_literals(
+    ctx: &Context,
+    num_constraints: usize,
+    height: usize,
+) -> Vec<((usize, usize), Bool<'_>)> {
+    (0..height)
+        .flat_map(|row| {
+            (0..num_constraints).map(move |i| {
+                let literal = Bool::new_const(ctx, format!("constraint[{}][{}]", i, row));
+                ((i, row), literal)
+            })
+        })
+        .collect()
+}
+
+/// Asserts every constraint on every row of `terms`, each guarded by its literal from
+/// `constraint_literals`. Guarding several traces with the same literals lets one assumption
+/// enable a constraint instance on all of them.
+pub(crate) fn assert_guarded_constraints<'ctx, E>(
+    solver: &Solver<'ctx>,
+    constraints: &[SymbolicExpression<E::F>],
+    terms: &TraceTerms<'_, E>,
+    literals: &[((usize, usize), Bool<'ctx>)],
+) where
+    E: FieldElement<'ctx>,
+{
+    let ctx = solver.get_context();
+    for row in 0..terms.main.height() {
+        let exps = parse_row_constraints(constraints, ctx, terms.row(row));
+        let row_literals = &literals[row * constraints.len()..(row + 1) * constraints.len()];
+        for (exp, (_, literal)) in exps.iter().zip(row_literals) {
+            solver.assert(&literal.implies(&terms.public_values.is_zero(ctx, exp)));
+        }
+    }
+}
+
+/// Whether every constraint holds on every row of `terms`, as a formula for callers that need to
+/// quantify over some of the terms. Universal public values are left for the caller to bind.
+pub(crate) fn constraints_hold<'ctx, E>(
+    ctx: &'ctx Context,
+    constraints: &[SymbolicExpression<E::F>],
+    terms: &TraceTerms<'_, E>,
+) -> Bool<'ctx>
+where
+    E: FieldElement<'ctx>,
+{
+    let holds = (0..terms.main.height())
+        .flat_map(|i| parse_row_constraints(constraints, ctx, terms.row(i)))
+        .map(|exp| exp.is_zero())
+        .collect::<Vec<_>>();
+    Bool::and(ctx, &holds)
+}
+
+/// The cells of `row` of an optional part of the trace, or none if it is absent.
+pub(crate) fn optional_row<E>(matrix: Option<&RowMajorMatrix<E>>, row: usize) -> &[E] {
+    match matrix {
+        Some(matrix) => matrix.row_slice(row),
+        None => &[],
+    }
+}
+
+/// Asserts every constraint on one row.
+pub(crate) fn assert_row_constraints<'ctx, E>(
+    solver: &Solver<'ctx>,
+    constraints: &[SymbolicExpression<E::F>],
+    row: Row<'_, E>,
+) where
+    E: FieldElement<'ctx>,
+{
+    parse_row_constraints(constraints, solver.get_context(), row)
+        .iter()
+        .for_each(|exp| row.public_values.assert_zero(solver, exp));
+}
+
+/// Reads the value the model assigns to each trace variable.
+pub(crate) fn extract_trace<'ctx, E>(
+    model: &Model<'ctx>,
+    vars: &RowMajorMatrix<E>,
+) -> RowMajorMatrix<E::F>
+where
+    E: FieldElement<'ctx>,
+{
+    RowMajorMatrix::new(
+        vars.values
+            .iter()
+            .map(|var| {
+                let value = model
+                    .eval(var, true)
+                    .and_then(|value| value.as_u64())
+                    .expect("model completion assigns every variable");
+                E::F::from_canonical_u64(value)
+            })
+            .collect(),
+        vars.width(),
+    )
+}
+
+/// The values the row selectors take on one row of the trace.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Selectors {
+    pub is_first_row: bool,
+    pub is_last_row: bool,
+    pub is_transition: bool,
+}
+
+impl Selectors {
+    pub(crate) fn for_row(row: usize, height: usize) -> Self {
+        Self {
+            is_first_row: row == 0,
+            is_last_row: row == height - 1,
+            is_transition: row != height - 1,
+        }
+    }
+}
+
+/// The terms a constraint can refer to when evaluated on one row of the trace.
+pub(crate) struct Row<'a, E> {
+    pub local: &'a [E],
+    /// The row after `local`.
+    pub next: &'a [E],
+    pub preprocessed_local: &'a [E],
+    pub preprocessed_next: &'a [E],
+    pub permutation_local: &'a [E],
+    pub permutation_next: &'a [E],
+    pub public_values: &'a PublicValueVars<E>,
+    pub challenges: &'a [E],
+    pub selectors: Selectors,
+}
+
+// Derived impls would require `E: Copy`, but a `Row` only borrows its terms.
+impl<E> Clone for Row<'_, E> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<E> Copy for Row<'_, E> {}
+
+impl<'a, E: Clone> Row<'a, E> {
+    /// Resolves a column of `ExtendedSymbolicAirBuilder`, laid out as in `TraceLayout`.
+    pub(crate) fn variable<F>(&self, var: &SymbolicVariable<F>) -> E {
+        let mut column = var.column;
+        for (local, next) in [
+            (self.local, self.next),
+            (self.preprocessed_local, self.preprocessed_next),
+            (self.permutation_local, self.permutation_next),
+        ] {
+            if column < local.len() {
+                let row = if var.is_next { next } else { local };
+                return row[column].clone();
+            }
+            column -= local.len();
+        }
+
+        let public_values = &self.public_values.values;
+        if column < public_values.len() {
+            public_values[column].clone()
+        } else {
+            self.challenges[column - public_values.len()].clone()
+        }
+    }
+}
+
+/// Translates the constraints evaluated on one row. `SymbolicAirBuilder` shares subexpressions
+/// through `Rc`, both within and across constraints, so each node is translated once per row and
+/// shared subtrees become a single Z3 term instead of being rebuilt at every use.
+pub(crate) fn parse_row_constraints<'ctx, E>(
+    constraints: &[SymbolicExpression<E::F>],
+    ctx: &'ctx Context,
+    row: Row<'_, E>,
+) -> Vec<E>
+where
+    E: FieldElement<'ctx>,
+{
+    let mut cache = HashMap::new();
+    constraints
+        .iter()
+        .map(|constraint| parse_symbolic_expression(constraint, ctx, row, &mut cache))
+        .collect()
+}
+
+/// Translates an expression evaluated on one row, reusing the terms of the nodes in `cache`.
+fn parse_symbolic_expression<'ctx, E>(
+    exp: &SymbolicExpression<E::F>,
+    ctx: &'ctx Context,
+    row: Row<'_, E>,
+    cache: &mut HashMap<*const SymbolicExpression<E::F>, E>,
+) -> E
+where
+    E: FieldElement<'ctx>,
+{
+    let key = exp as *const _;
+    if let Some(term) = cache.get(&key) {
+        return term.clone();
+    }
+
+    let mut parse =
+        |exp: &SymbolicExpression<E::F>| parse_symbolic_expression(exp, ctx, row, cache);
+    let term = match exp {
+        SymbolicExpression::Variable(var) => row.variable(var),
+        SymbolicExpression::IsFirstRow => E::from_bool(ctx, row.selectors.is_first_row),
+        SymbolicExpression::IsLastRow => E::from_bool(ctx, row.selectors.is_last_row),
+        SymbolicExpression::IsTransition => E::from_bool(ctx, row.selectors.is_transition),
+        SymbolicExpression::Constant(f) => E::from_f(ctx, *f),
+        SymbolicExpression::Add { x, y, .. } => parse(x) + parse(y),
+        SymbolicExpression::Sub { x, y, .. } => parse(x) - parse(y),
+        SymbolicExpression::Neg { x, .. } => -parse(x),
+        SymbolicExpression::Mul { x, y, .. } => parse(x) * parse(y),
+    };
+    cache.insert(key, term.clone());
+    term
+}
@@ -0,0 +1,134 @@
+use core::ops::Range;
+
+use p3_air::{AirBuilder, PairBuilder};
+use p3_field::Field;
+use p3_matrix::dense::RowMajorMatrix;
+use p3_matrix::MatrixRowSlices;
+use p3_uni_stark::{SymbolicAirBuilder, SymbolicExpression, SymbolicVariable};
+
+use crate::air::{AirBuilderWithPublicValues, MultiStageAirBuilder};
+
+/// The widths of the parts of the trace an AIR can refer to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TraceLayout {
+    pub width: usize,
+    pub preprocessed_width: usize,
+    /// The width of the second stage, committed to after the challenges are drawn.
+    pub permutation_width: usize,
+    pub num_public_values: usize,
+    pub num_challenges: usize,
+}
+
+impl TraceLayout {
+    /// The number of columns of the inner `SymbolicAirBuilder`.
+    pub fn total_width(&self) -> usize {
+        self.width
+            + self.preprocessed_width
+            + self.permutation_width
+            + self.num_public_values
+            + self.num_challenges
+    }
+}
+
+/// A `SymbolicAirBuilder` that also hands out preprocessed columns, a second trace stage, public
+/// values and challenges.
+///
+/// `SymbolicExpression` can only refer to columns of the main trace, so the inner builder is made
+/// wider than the main trace. Its columns are laid out as the main trace, then the preprocessed
+/// columns, the permutation columns, one column per public value and one per challenge, as in
+/// `TraceLayout`. When translating to Z3, a column past the main trace resolves to the matching
+/// cell, public value or challenge.
+pub struct ExtendedSymbolicAirBuilder<F: Field> {
+    inner: SymbolicAirBuilder<F>,
+    layout: TraceLayout,
+    main: RowMajorMatrix<SymbolicVariable<F>>,
+    /// The `local` and `next` rows of the preprocessed columns.
+    preprocessed: Vec<SymbolicVariable<F>>,
+    /// The `local` and `next` rows of the permutation columns.
+    permutation: Vec<SymbolicVariable<F>>,
+    public_values: Vec<SymbolicVariable<F>>,
+    challenges: Vec<SymbolicVariable<F>>,
+}
+
+impl<F: Field> ExtendedSymbolicAirBuilder<F> {
+    pub fn new(layout: TraceLayout) -> Self {
+        let inner = SymbolicAirBuilder::new(layout.total_width());
+        let columns = inner.main();
+        let (local, next) = (columns.row_slice(0), columns.row_slice(1));
+
+        let mut start = 0;
+        let mut take = |len: usize| {
+            start += len;
+            start - len..start
+        };
+        let rows = |range: Range<usize>| [&local[range.clone()], &next[range]].concat();
+
+        let main = RowMajorMatrix::new(rows(take(layout.width)), layout.width);
+        let preprocessed = rows(take(layout.preprocessed_width));
+        let permutation = rows(take(layout.permutation_width));
+        let public_values = local[take(layout.num_public_values)].to_vec();
+        let challenges = local[take(layout.num_challenges)].to_vec();
+        Self {
+            inner,
+            layout,
+            main,
+            preprocessed,
+            permutation,
+            public_values,
+            challenges,
+        }
+    }
+
+    pub fn constraints(&self) -> Vec<SymbolicExpression<F>> {
+        self.inner.constraints()
+    }
+}
+
+impl<F: Field> AirBuilder for ExtendedSymbolicAirBuilder<F> {
+    type F = F;
+    type Expr = SymbolicExpression<F>;
+    type Var = SymbolicVariable<F>;
+    type M = RowMajorMatrix<SymbolicVariable<F>>;
+
+    fn main(&self) -> Self::M {
+        self.main.clone()
+    }
+
+    fn is_first_row(&self) -> Self::Expr {
+        self.inner.is_first_row()
+    }
+
+    fn is_last_row(&self) -> Self::Expr {
+        self.inner.is_last_row()
+    }
+
+    fn is_transition_window(&self, size: usize) -> Self::Expr {
+        self.inner.is_transition_window(size)
+    }
+
+    fn assert_zero<I: Into<Self::Expr>>(&mut self, x: I) {
+        self.inner.assert_zero(x);
+    }
+}
+
+impl<F: Field> PairBuilder for ExtendedSymbolicAirBuilder<F> {
+    fn preprocessed(&self) -> Self::M {
+        RowMajorMatrix::new(self.preprocessed.clone(), self.layout.preprocessed_width)
+    }
+}
+
+impl<F: Field> AirBuilderWithPublicValues for ExtendedSymbolicAirBuilder<F> {
+    fn public_values(&self) -> &[Self::Var] {
+        &self.public_values
+    }
+}
+
+impl<F: Field> MultiStageAirBuilder for ExtendedSymbolicAirBuilder<F> {
+    fn permutation(&self) -> Self::M {
+        RowMajorMatrix::new(self.permutation.clone(), self.layout.permutation_width)
+    }
+
+    fn challenges(&self) -> &[Self::Var] {
+        &self.challenges
+    }
+}
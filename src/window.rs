@@ -0,0 +1,215 @@
+use core::ops::Range;
+
+use p3_air::Air;
+use p3_field::PrimeField64;
+use p3_matrix::dense::RowMajorMatrix;
+use p3_matrix::{Matrix, MatrixGet, MatrixRowSlices};
+use p3_uni_stark::SymbolicExpression;
+use z3::ast::{Ast, Bool};
+use z3::{Context, SatResult};
+
+use crate::check_unconstrained::{
+    check_preprocessed_height, dispatch_encoding, CheckConfig, CheckError,
+};
+use crate::context::context;
+use crate::field::FieldElement;
+use crate::symbolic::{
+    assert_row_constraints, extract_trace, new_permutation_vars, optional_row,
+    symbolic_constraints, trace_consts, Row, Selectors, SharedTerms,
+};
+use crate::symbolic_builder::ExtendedSymbolicAirBuilder;
+
+/// What the rows just outside a window are fixed to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Boundary {
+    /// The neighbouring rows keep their honest values. Any freedom found is real, since the rest
+    /// of the trace stays honest, but a determined row may still move together with rows outside
+    /// its window.
+    Pinned,
+    /// The neighbouring rows are left symbolic. A determined row is determined in every trace,
+    /// but freedom found may rely on neighbours that no full trace allows.
+    Symbolic,
+}
+
+/// Whether a row of the honest trace is forced by the constraints within its window.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RowStatus {
+    Determined,
+    /// Some assignment to the window changes a non-input cell of this row.
+    Free,
+    /// The solver gave up on this row.
+    Unknown,
+}
+
+/// Checks uniqueness one window of `window_size` rows at a time, rather than solving for the
+/// whole trace at once. Only the constraints touching a window and the rows they reach are sent
+/// to the solver, which relies on constraints referring to no rows but `local` and `next`.
+pub fn check_windows<F, A>(
+    air: &A,
+    main: &RowMajorMatrix<F>,
+    preprocessed: Option<&RowMajorMatrix<F>>,
+    window_size: usize,
+    boundary: Boundary,
+    config: &CheckConfig,
+) -> Result<Vec<RowStatus>, CheckError>
+where
+    F: PrimeField64,
+    A: Air<ExtendedSymbolicAirBuilder<F>>,
+{
+    assert!(window_size > 0, "windows must contain at least one row");
+    check_preprocessed_height(main.height(), preprocessed)?;
+
+    let preprocessed_width = preprocessed.map_or(0, |p| p.width());
+    let constraints = symbolic_constraints(air, config.layout(main.width(), preprocessed_width));
+    let statuses = dispatch_encoding!(
+        config.encoding,
+        F,
+        check_all_windows(
+            context(),
+            &constraints,
+            main,
+            preprocessed,
+            window_size,
+            boundary,
+            config,
+        )
+    );
+    Ok(statuses)
+}
+
+fn check_all_windows<'ctx, E>(
+    ctx: &'ctx Context,
+    constraints: &[SymbolicExpression<E::F>],
+    main: &RowMajorMatrix<E::F>,
+    preprocessed: Option<&RowMajorMatrix<E::F>>,
+    window_size: usize,
+    boundary: Boundary,
+    config: &CheckConfig,
+) -> Vec<RowStatus>
+where
+    E: FieldElement<'ctx>,
+{
+    let height = main.height();
+    let preprocessed = preprocessed.map(|p| trace_consts::<E>(ctx, p));
+
+    (0..height)
+        .step_by(window_size)
+        .flat_map(|start| {
+            let window = start..(start + window_size).min(height);
+            let preprocessed = preprocessed.as_ref();
+            check_window(
+                ctx,
+                constraints,
+                main,
+                preprocessed,
+                window,
+                boundary,
+                config,
+            )
+        })
+        .collect()
+}
+
+fn check_window<'ctx, E>(
+    ctx: &'ctx Context,
+    constraints: &[SymbolicExpression<E::F>],
+    main: &RowMajorMatrix<E::F>,
+    preprocessed: Option<&RowMajorMatrix<E>>,
+    window: Range<usize>,
+    boundary: Boundary,
+    config: &CheckConfig,
+) -> Vec<RowStatus>
+where
+    E: FieldElement<'ctx>,
+{
+    let solver = config.solver(ctx);
+
+    let width = main.width();
+    let height = main.height();
+
+    // The constraints on `before` reach into the window, and those on its last row reach `after`.
+    let before = (window.start + height - 1) % height;
+    let after = window.end % height;
+    let mut rows = window.clone().collect::<Vec<_>>();
+    for row in [before, after] {
+        if !rows.contains(&row) {
+            rows.push(row);
+        }
+    }
+    let position = |row: usize| rows.iter().position(|&r| r == row).unwrap();
+
+    let vars = RowMajorMatrix::new(
+        rows.iter()
+            .flat_map(|&row| (0..width).map(move |col| (row, col)))
+            .map(|(row, col)| {
+                let symbolic = window.contains(&row) || boundary == Boundary::Symbolic;
+                if symbolic && !config.is_input(row, col) {
+                    E::new_const(&solver, format!("T[{}][{}]", row, col))
+                } else {
+                    E::from_f(ctx, main.get(row, col))
+                }
+            })
+            .collect(),
+        width,
+    );
+
+    // The second stage has no honest values, so it is symbolic even outside the window. The
+    // preprocessed columns are lifted once for every window, in `check_all_windows`.
+    let permutation =
+        new_permutation_vars(&solver, "S", config.permutation_width, rows.len(), false);
+    let shared = SharedTerms::<E>::with_preprocessed(&solver, None, config, false);
+    let mut constraint_rows = window.clone().collect::<Vec<_>>();
+    if !window.contains(&before) {
+        constraint_rows.push(before);
+    }
+    for row in constraint_rows {
+        let row = Row {
+            local: vars.row_slice(position(row)),
+            next: vars.row_slice(position((row + 1) % height)),
+            preprocessed_local: optional_row(preprocessed, row),
+            preprocessed_next: optional_row(preprocessed, (row + 1) % height),
+            permutation_local: optional_row(permutation.as_ref(), position(row)),
+            permutation_next: optional_row(permutation.as_ref(), position((row + 1) % height)),
+            public_values: &shared.public_values,
+            challenges: &shared.challenges,
+            selectors: Selectors::for_row(row, height),
+        };
+        assert_row_constraints(&solver, constraints, row);
+    }
+
+    let mut statuses = vec![None; window.len()];
+    for (i, row) in window.clone().enumerate() {
+        if statuses[i].is_some() {
+            continue;
+        }
+
+        let differs = (0..width)
+            .map(|col| {
+                let honest = E::from_f(ctx, main.get(row, col));
+                vars.get(position(row), col)._eq(&honest).not()
+            })
+            .collect::<Vec<_>>();
+
+        solver.push();
+        solver.assert(&Bool::or(ctx, &differs));
+        let status = match solver.check() {
+            SatResult::Sat => {
+                // Every window row moved by this model is free too.
+                let model = solver.get_model().unwrap();
+                let trace = extract_trace(&model, &vars);
+                for (j, other) in window.clone().enumerate() {
+                    if trace.row_slice(position(other)) != main.row_slice(other) {
+                        statuses[j] = Some(RowStatus::Free);
+                    }
+                }
+                RowStatus::Free
+            }
+            SatResult::Unsat => RowStatus::Determined,
+            SatResult::Unknown => RowStatus::Unknown,
+        };
+        statuses[i] = Some(status);
+        solver.pop(1);
+    }
+
+    statuses.into_iter().map(Option::unwrap).collect()
+}
@@ -0,0 +1,84 @@
+use p3_air::{Air, AirBuilder};
+use p3_field::PrimeField64;
+use p3_matrix::dense::RowMajorMatrix;
+use p3_matrix::{Matrix, MatrixRowSlices};
+use z3::Solver;
+
+use crate::field::Felt;
+use crate::symbolic::Selectors;
+
+/// An `AirBuilder` that evaluates an AIR directly on Z3 terms, asserting each constraint on a
+/// solver as `Air::eval` emits it, without going through `SymbolicExpression`.
+///
+/// `AirBuilder::Var` must be `Copy`, which a Z3 term is not, so the variables are references to
+/// the cells of a trace of `Felt`s and only the expressions own their terms. `Felt` implements
+/// `AbstractField` in the global context, so the trace must live there too.
+pub struct Z3AirBuilder<'a, F: PrimeField64> {
+    solver: &'a Solver<'static>,
+    /// The `local` and `next` rows.
+    main: RowMajorMatrix<&'a Felt<'static, F>>,
+    selectors: Selectors,
+}
+
+impl<'a, F: PrimeField64> Z3AirBuilder<'a, F> {
+    /// A builder for row `row` of `vars`, with the last row wrapping around to the first.
+    pub fn new(
+        solver: &'a Solver<'static>,
+        vars: &'a RowMajorMatrix<Felt<'static, F>>,
+        row: usize,
+    ) -> Self {
+        let height = vars.height();
+        let local = vars.row_slice(row).iter();
+        let next = vars.row_slice((row + 1) % height).iter();
+        Self {
+            solver,
+            main: RowMajorMatrix::new(local.chain(next).collect(), vars.width()),
+            selectors: Selectors::for_row(row, height),
+        }
+    }
+}
+
+impl<'a, F: PrimeField64> AirBuilder for Z3AirBuilder<'a, F> {
+    type F = F;
+    type Expr = Felt<'static, F>;
+    type Var = &'a Felt<'static, F>;
+    type M = RowMajorMatrix<&'a Felt<'static, F>>;
+
+    fn main(&self) -> Self::M {
+        self.main.clone()
+    }
+
+    fn is_first_row(&self) -> Self::Expr {
+        Felt::from_bool(self.solver.get_context(), self.selectors.is_first_row)
+    }
+
+    fn is_last_row(&self) -> Self::Expr {
+        Felt::from_bool(self.solver.get_context(), self.selectors.is_last_row)
+    }
+
+    fn is_transition_window(&self, size: usize) -> Self::Expr {
+        if size == 2 {
+            Felt::from_bool(self.solver.get_context(), self.selectors.is_transition)
+        } else {
+            panic!("uni-stark only supports a window size of 2")
+        }
+    }
+
+    fn assert_zero<I: Into<Self::Expr>>(&mut self, x: I) {
+        x.into().assert_zero(self.solver);
+    }
+}
+
+/// Asserts the constraints of `air` on every row of `vars`, by evaluating it with `Z3AirBuilder`.
+pub fn assert_air_constraints<F, A>(
+    solver: &Solver<'static>,
+    air: &A,
+    vars: &RowMajorMatrix<Felt<'static, F>>,
+) where
+    F: PrimeField64,
+    A: for<'a> Air<Z3AirBuilder<'a, F>>,
+{
+    for row in 0..vars.height() {
+        air.eval(&mut Z3AirBuilder::new(solver, vars, row));
+    }
+}